@@ -0,0 +1,344 @@
+//! This module provides `RoboclawBuilder`, which opens the serial port and sets up
+//! a `Roboclaw` in one go:
+//!
+//! ```no_run
+//! # fn main() -> Result<(), roboclaw::errors::RoboClawError> {
+//! use roboclaw::Roboclaw;
+//!
+//! let mut roboclaw = Roboclaw::builder()
+//!     .port("/dev/ttyACM0")
+//!     .baud_rate(115_200)
+//!     .address(0x81)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! It also defines the `Timeouts` the connection waits for responses, per
+//! `schema::CommandClass`.
+
+use crate::{
+    connection::Connection,
+    errors::{ConnectionError, RoboClawError},
+    port::{PortSpec, ROBOCLAW_USB_PID, ROBOCLAW_USB_VID},
+    reconnect::{ConnectionEvent, EventCallback, ReconnectingTransport},
+    retry::RetryPolicy,
+    schema::CommandClass,
+    trace::TraceSink,
+    transport::Transport,
+    Roboclaw,
+};
+use std::time::Duration;
+
+/// The lowest packet serial address a RoboClaw can be configured to.
+pub const MIN_ADDRESS: u8 = 0x80;
+/// The highest packet serial address a RoboClaw can be configured to.
+pub const MAX_ADDRESS: u8 = 0x87;
+/// The baud rate used if none is set, the RoboClaw's factory setting.
+pub const DEFAULT_BAUD_RATE: u32 = 38_400;
+
+/// How long to wait for a response, per class of command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    pub read: Duration,   // Commands answered with data
+    pub write: Duration,  // Commands acknowledged with 0xFF
+    pub eeprom: Duration, // Commands reading or writing the EEPROM
+}
+
+impl Timeouts {
+    /// Returns timeouts suited to `baud_rate`: the default timeouts plus the time
+    /// the longest exchange takes on the wire.
+    pub fn for_baud_rate(baud_rate: u32) -> Self {
+        // A byte takes 10 bits on the wire, the longest fixed-size request and
+        // response are both below 40 bytes including address, command and CRC.
+        let transfer = Duration::from_micros(400 * 1_000_000 / baud_rate.max(1) as u64);
+        let defaults = Timeouts::default();
+        Timeouts {
+            read: defaults.read + transfer,
+            write: defaults.write + transfer,
+            eeprom: defaults.eeprom + transfer,
+        }
+    }
+
+    /// Returns the timeout for commands of `class`.
+    pub fn get(&self, class: CommandClass) -> Duration {
+        match class {
+            CommandClass::Read => self.read,
+            CommandClass::Write => self.write,
+            CommandClass::Eeprom => self.eeprom,
+        }
+    }
+}
+
+impl Default for Timeouts {
+    /// 5 ms for reads and writes, 100 ms for EEPROM access.
+    fn default() -> Self {
+        Timeouts {
+            read: Duration::from_millis(5),
+            write: Duration::from_millis(5),
+            eeprom: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Where the builder gets its transport from.
+enum Source {
+    None,                          // Nothing set yet
+    Spec(PortSpec),                // A port to open
+    Transport(Box<dyn Transport>), // A transport opened by the caller
+}
+
+/// Builds a `Roboclaw`, see the module documentation.
+pub struct RoboclawBuilder {
+    source: Source,                    // Where to get the transport from
+    baud_rate: Option<u32>,            // Baud rate replacing the one of the port spec
+    address: u8,                       // Packet serial address of the RoboClaw
+    timeouts: Option<Timeouts>,        // Response timeouts, derived from the baud rate if unset
+    retry_policy: RetryPolicy,         // Retry policy of the connection
+    trace: Option<Box<dyn TraceSink>>, // Trace sink, if tracing is wanted
+    reconnect: bool,                   // Whether to reopen the port after it disconnected
+    on_event: Option<EventCallback>,   // Receives the events of the reconnecting port
+    probe: bool,                       // Whether to probe the capabilities when building
+}
+
+impl RoboclawBuilder {
+    /// Creates a builder for the RoboClaw at address 0x80, at 38400 baud with the
+    /// default retry policy.
+    pub fn new() -> Self {
+        RoboclawBuilder {
+            source: Source::None,
+            baud_rate: None,
+            address: MIN_ADDRESS,
+            timeouts: None,
+            retry_policy: RetryPolicy::default(),
+            trace: None,
+            reconnect: false,
+            on_event: None,
+            probe: true,
+        }
+    }
+
+    /// Opens the port at the device `path`.
+    pub fn port<S: Into<String>>(mut self, path: S) -> Self {
+        self.source = Source::Spec(PortSpec::path(path, DEFAULT_BAUD_RATE));
+        self
+    }
+
+    /// Opens the RoboClaw with the USB `serial_number`.
+    pub fn usb(mut self, serial_number: &str) -> Self {
+        self.source = Source::Spec(PortSpec::usb(
+            ROBOCLAW_USB_VID,
+            ROBOCLAW_USB_PID,
+            Some(serial_number.to_owned()),
+            DEFAULT_BAUD_RATE,
+        ));
+        self
+    }
+
+    /// Opens the port described by `spec`. A baud rate set on the builder replaces
+    /// the one in `spec`.
+    pub fn spec(mut self, spec: PortSpec) -> Self {
+        self.source = Source::Spec(spec);
+        self
+    }
+
+    /// Uses a transport opened by the caller instead of opening a port. The baud
+    /// rate and reconnect settings do not apply to it.
+    pub fn transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.source = Source::Transport(Box::new(transport));
+        self
+    }
+
+    /// Sets the baud rate of the port. Defaults to `DEFAULT_BAUD_RATE`.
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = Some(baud_rate);
+        self
+    }
+
+    /// Sets the packet serial address, between `MIN_ADDRESS` and `MAX_ADDRESS`.
+    pub fn address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Sets the response timeouts. Defaults to `Timeouts::for_baud_rate`.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = Some(timeouts);
+        self
+    }
+
+    /// Sets the retry policy.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Traces every frame into `sink`, see `Roboclaw::set_trace`.
+    pub fn trace<S: TraceSink + 'static>(mut self, sink: S) -> Self {
+        self.trace = Some(Box::new(sink));
+        self
+    }
+
+    /// Reopens the port after it disconnected, see `reconnect::ReconnectingTransport`.
+    pub fn reconnect(mut self, reconnect: bool) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Reopens the port after it disconnected and passes every `ConnectionEvent` to
+    /// `on_event`, see `reconnect::ReconnectingTransport::on_event`. Enables
+    /// `reconnect`.
+    pub fn on_connection_event<F: FnMut(&ConnectionEvent) + Send + 'static>(
+        mut self,
+        on_event: F,
+    ) -> Self {
+        self.reconnect = true;
+        self.on_event = Some(Box::new(on_event));
+        self
+    }
+
+    /// Probes the capabilities of the RoboClaw when building, see
+    /// `Roboclaw::capabilities`. Enabled by default, which also confirms that a
+    /// RoboClaw answers at the address. When disabled, they are probed on first use.
+    pub fn probe(mut self, probe: bool) -> Self {
+        self.probe = probe;
+        self
+    }
+
+    /// Validates the settings, opens the port, creates the `Roboclaw` and probes
+    /// its capabilities.
+    pub fn build(self) -> Result<Roboclaw, RoboClawError> {
+        if !(MIN_ADDRESS..=MAX_ADDRESS).contains(&self.address) {
+            return Err(RoboClawError::InvalidAddress {
+                address: self.address,
+            });
+        }
+        let (spec, transport) = match self.source {
+            Source::None => {
+                return Err(serial_error(serialport::Error::new(
+                    serialport::ErrorKind::InvalidInput,
+                    "no port or transport was given",
+                )))
+            }
+            Source::Spec(spec) => match self.baud_rate {
+                Some(baud_rate) => (Some(with_baud_rate(spec, baud_rate)), None),
+                None => (Some(spec), None),
+            },
+            Source::Transport(transport) => (None, Some(transport)),
+        };
+        let baud_rate = spec.as_ref().map_or(DEFAULT_BAUD_RATE, PortSpec::baud_rate);
+        let timeouts = self
+            .timeouts
+            .unwrap_or_else(|| Timeouts::for_baud_rate(baud_rate));
+
+        let transport: Box<dyn Transport> = match (spec, transport) {
+            (_, Some(transport)) => transport,
+            (Some(spec), None) if self.reconnect => {
+                let transport = ReconnectingTransport::open(spec).map_err(serial_error)?;
+                match self.on_event {
+                    Some(on_event) => Box::new(transport.on_boxed_event(on_event)),
+                    None => Box::new(transport),
+                }
+            }
+            (Some(spec), None) => Box::new(spec.open(timeouts.read).map_err(serial_error)?),
+            (None, None) => unreachable!("every source yields a spec or a transport"),
+        };
+
+        let mut connection = Connection::new(transport, self.address, self.retry_policy)?;
+        connection.timeouts = timeouts;
+        connection.set_trace(self.trace);
+        let mut roboclaw = Roboclaw::from_connection(connection);
+        if self.probe {
+            roboclaw.probe()?;
+        }
+        Ok(roboclaw)
+    }
+}
+
+impl Default for RoboclawBuilder {
+    fn default() -> Self {
+        RoboclawBuilder::new()
+    }
+}
+
+/// Returns `spec` with its baud rate replaced by `baud_rate`.
+fn with_baud_rate(spec: PortSpec, baud_rate: u32) -> PortSpec {
+    match spec {
+        PortSpec::Path { path, .. } => PortSpec::Path { path, baud_rate },
+        PortSpec::Usb {
+            vid,
+            pid,
+            serial_number,
+            ..
+        } => PortSpec::Usb {
+            vid,
+            pid,
+            serial_number,
+            baud_rate,
+        },
+    }
+}
+
+/// Wraps an error opening the port. It is not converted with `From`, which would
+/// report a missing device as a disconnect.
+fn serial_error(error: serialport::Error) -> RoboClawError {
+    RoboClawError::Connection(ConnectionError::Serial(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayTransport;
+
+    fn build_at(address: u8) -> Result<Roboclaw, RoboClawError> {
+        RoboclawBuilder::new()
+            .transport(ReplayTransport::from_frames(vec![]))
+            .address(address)
+            .probe(false)
+            .build()
+    }
+
+    #[test]
+    fn addresses_outside_the_packet_serial_range_are_rejected() {
+        assert!(build_at(0x80).is_ok());
+        assert!(build_at(0x87).is_ok());
+        for address in [0x7F, 0x88] {
+            assert!(matches!(
+                build_at(address),
+                Err(RoboClawError::InvalidAddress { address: rejected }) if rejected == address
+            ));
+        }
+    }
+
+    #[test]
+    fn a_port_or_transport_is_required() {
+        assert!(matches!(
+            RoboclawBuilder::new().build(),
+            Err(RoboClawError::Connection(ConnectionError::Serial(_)))
+        ));
+    }
+
+    #[test]
+    fn timeouts_grow_with_the_transfer_time() {
+        let transfer = Duration::from_micros(10_416);
+        let timeouts = Timeouts::for_baud_rate(DEFAULT_BAUD_RATE);
+        assert_eq!(
+            timeouts.get(CommandClass::Read),
+            Duration::from_millis(5) + transfer
+        );
+        assert_eq!(
+            timeouts.get(CommandClass::Write),
+            Duration::from_millis(5) + transfer
+        );
+        assert_eq!(
+            timeouts.get(CommandClass::Eeprom),
+            Duration::from_millis(100) + transfer
+        );
+        assert!(Timeouts::for_baud_rate(460_800).read < timeouts.read);
+        // A baud rate of zero does not divide by zero.
+        assert_eq!(
+            Timeouts::for_baud_rate(0).write,
+            Duration::from_millis(5) + Duration::from_secs(400)
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! This module describes what the connected RoboClaw model can do.
+//!
+//! The firmware string names the model as well as the version, e.g.
+//! `"USB Roboclaw 2x15a v4.1.34"`. `Capabilities` combines both: the firmware
+//! version decides which commands exist at all, the model how many motor channels
+//! there are, whether a second temperature sensor is fitted and how much current
+//! the channels can deliver. `Roboclaw::capabilities` probes them once and
+//! `Roboclaw` consults them to reject unsupported commands and arguments before
+//! anything is sent.
+
+use crate::{firmware::FirmwareVersion, schema::SCHEMA, Commands};
+use std::fmt;
+
+/// A RoboClaw model, as named in its firmware string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Model {
+    Dual {
+        amps: u16, // Rated continuous current per channel
+    }, // A two channel RoboClaw, e.g. the 2x15A.
+    Solo {
+        amps: u16, // Rated continuous current
+    }, // A single channel RoboClaw Solo.
+    Unknown, // A firmware string without a recognised model.
+}
+
+impl Model {
+    /// Parses the model out of a firmware string as returned by
+    /// `Roboclaw::read_version`.
+    pub fn parse(version: &str) -> Self {
+        let words: Vec<String> = version
+            .split_whitespace()
+            .map(str::to_ascii_lowercase)
+            .collect();
+        let amps = |word: &str| word.strip_suffix('a')?.parse().ok();
+        for (i, word) in words.iter().enumerate() {
+            if let Some(amps) = word.strip_prefix("2x").and_then(amps) {
+                return Model::Dual { amps };
+            }
+            if word == "solo" {
+                if let Some(amps) = words.get(i + 1).and_then(|word| amps(word)) {
+                    return Model::Solo { amps };
+                }
+            }
+        }
+        Model::Unknown
+    }
+
+    /// Returns the number of motor channels, or `None` for an unknown model.
+    pub fn channels(&self) -> Option<u8> {
+        match self {
+            Model::Dual { .. } => Some(2),
+            Model::Solo { .. } => Some(1),
+            Model::Unknown => None,
+        }
+    }
+
+    /// Returns the peak current per channel in units of 10 mA, or `None` if it is
+    /// not known for this model.
+    pub fn peak_current(&self) -> Option<u32> {
+        let amps = match self {
+            Model::Dual { amps: 7 } => 15,
+            Model::Dual { amps: 15 } => 30,
+            Model::Dual { amps: 30 } => 60,
+            Model::Dual { amps: 45 } => 60,
+            Model::Dual { amps: 60 } => 120,
+            Model::Dual { amps: 160 } => 240,
+            _ => return None,
+        };
+        Some(amps * 100)
+    }
+
+    /// Returns `true` if the model has the second temperature sensor read by
+    /// `Roboclaw::read_temperature_2`. Unknown models are assumed to have it.
+    pub fn has_temperature_2(&self) -> bool {
+        match self {
+            Model::Dual { amps } | Model::Solo { amps } => *amps >= 60,
+            Model::Unknown => true,
+        }
+    }
+
+    /// Returns `true` if the model supports `command`, regardless of the firmware
+    /// version. Unknown models are assumed to support every command.
+    pub fn supports(&self, command: Commands) -> bool {
+        match command {
+            Commands::ReadTemperature2 => self.has_temperature_2(),
+            command if uses_second_channel(command) => self.channels() != Some(1),
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Model::Dual { amps } => write!(f, "RoboClaw 2x{}A", amps),
+            Model::Solo { amps } => write!(f, "RoboClaw Solo {}A", amps),
+            Model::Unknown => write!(f, "unknown RoboClaw"),
+        }
+    }
+}
+
+/// What the connected RoboClaw supports, see the module documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub model: Model,              // The model named in the firmware string
+    pub firmware: FirmwareVersion, // The firmware version
+    pub max_current: Option<u32>,  // Peak current per channel in units of 10 mA, if known
+    pub has_temperature_2: bool,   // Whether a second temperature sensor is fitted
+}
+
+impl Capabilities {
+    /// Derives the capabilities of `model` running `firmware`.
+    pub fn new(model: Model, firmware: FirmwareVersion) -> Self {
+        Capabilities {
+            model,
+            firmware,
+            max_current: model.peak_current(),
+            has_temperature_2: model.has_temperature_2(),
+        }
+    }
+
+    /// Returns `true` if both the firmware and the model support `command`.
+    pub fn supports(&self, command: Commands) -> bool {
+        command
+            .min_firmware()
+            .is_none_or(|required| self.firmware >= required)
+            && self.model.supports(command)
+    }
+
+    /// Returns every supported command, in order of command number.
+    pub fn supported_commands(&self) -> impl Iterator<Item = Commands> + '_ {
+        SCHEMA
+            .iter()
+            .map(|schema| schema.command)
+            .filter(move |command| self.supports(*command))
+    }
+}
+
+/// Returns `true` for commands that drive, configure or read the second motor
+/// channel, including the mixed mode commands.
+fn uses_second_channel(command: Commands) -> bool {
+    use Commands::*;
+    matches!(
+        command,
+        M2Forward
+            | M2Backward
+            | M2Drive
+            | MixDriveForward
+            | MixDriveBackward
+            | MixTurnRight
+            | MixTurnLeft
+            | MixDrive
+            | TurnLeftRight
+            | M2ReadEncoder
+            | M2ReadSpeedCPS
+            | M2SetEncoder
+            | M2SetVelocityPIDConst
+            | M2ReadRawSpeed
+            | M2DriveSignedDutyCycle
+            | MixDriveSignedDutyCycle
+            | M2DriveSignedSpeed
+            | MixDriveSignedSpeed
+            | M2DriveSignedSpeedAccel
+            | MixDriveSignedSpeedAccel
+            | M2DriveSignedSpeedDistanceBuffered
+            | MixDriveSignedSpeedDistanceBuffered
+            | M2DriveSignedSpeedAccelDistanceBuffered
+            | MixDriveSignedSpeedAccelDistanceBuffered
+            | MixDriveIndividualSignedSpeedAccel
+            | MixDriveIndividualSignedSpeedAccelDistance
+            | M2DriveSignedDutyAccel
+            | MixDriveSignedDutyAccel
+            | M2ReadVelocityPIDConsts
+            | M2SetPositionPIDConsts
+            | M2ReadPositionPIDConsts
+            | M2DriveSpeedAccelDeccelPosition
+            | MixDriveSpeedAccelDeccelPosition
+            | M2SetDefaultDutyCycle
+            | M2SetDefaultSpeed
+            | M2SetEncoderMode
+            | M2SetAutoHomeDutySpeedTimeout
+            | M2DrivePosition
+            | MixDrivePosition
+            | M2DriveSpeedPosition
+            | MixDriveSpeedPosition
+            | M2SetMaxCurrent
+            | M2ReadMaxCurrent
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        errors::RoboClawError,
+        motor::{Duty, Motor},
+        replay::{tests::*, ReplayTransport},
+        Roboclaw,
+    };
+
+    #[test]
+    fn models_are_parsed_from_the_firmware_string() {
+        for (amps, peak) in [
+            (7, 1500),
+            (15, 3000),
+            (30, 6000),
+            (45, 6000),
+            (60, 12000),
+            (160, 24000),
+        ] {
+            let model = Model::parse(&format!("USB Roboclaw 2x{}a v4.1.34\n", amps));
+            assert_eq!(model, Model::Dual { amps });
+            assert_eq!(model.peak_current(), Some(peak));
+            assert_eq!(model.channels(), Some(2));
+            assert_eq!(model.has_temperature_2(), amps >= 60);
+        }
+        assert_eq!(
+            Model::parse("RoboClaw 2X15A v4.1.34"),
+            Model::Dual { amps: 15 }
+        );
+
+        let solo = Model::parse("USB RoboClaw Solo 60A v4.1.34");
+        assert_eq!(solo, Model::Solo { amps: 60 });
+        assert_eq!(solo.channels(), Some(1));
+        assert_eq!(solo.peak_current(), None);
+        assert_eq!(solo.to_string(), "RoboClaw Solo 60A");
+
+        for version in [
+            "USB Roboclaw v4.1.34",
+            "RoboClaw Solo v4.1.34",
+            "RoboClaw 2xa v4.1.34",
+        ] {
+            let model = Model::parse(version);
+            assert_eq!(model, Model::Unknown, "{:?}", version);
+            assert_eq!(model.channels(), None);
+            assert!(model.has_temperature_2());
+        }
+    }
+
+    #[test]
+    fn models_reject_channels_and_sensors_they_lack() {
+        let firmware = FirmwareVersion::new(4, 1, 34);
+        let solo = Capabilities::new(Model::Solo { amps: 60 }, firmware);
+        assert!(solo.supports(Commands::M1DriveSignedDutyCycle));
+        assert!(solo.supports(Commands::ReadTemperature2));
+        assert!(!solo.supports(Commands::M2DriveSignedDutyCycle));
+        assert!(!solo.supports(Commands::MixDriveSignedDutyCycle));
+        assert!(!solo
+            .supported_commands()
+            .any(|command| command == Commands::M2ReadEncoder));
+
+        let small = Capabilities::new(Model::Dual { amps: 15 }, firmware);
+        assert!(small.supports(Commands::M2DriveSignedDutyCycle));
+        assert!(!small.supports(Commands::ReadTemperature2));
+
+        let unknown = Capabilities::new(Model::Unknown, firmware);
+        assert_eq!(unknown.supported_commands().count(), SCHEMA.len());
+    }
+
+    #[test]
+    fn unsupported_commands_are_rejected_before_sending() {
+        let frames = read(
+            Commands::ReadFirmwareVersion,
+            b"USB RoboClaw Solo 60A v4.1.34\n\0",
+        );
+        let transport = ReplayTransport::from_frames(frames);
+        let handle = transport.handle();
+        let mut roboclaw = Roboclaw::from_transport(transport, ADDRESS).unwrap();
+        roboclaw.probe().unwrap();
+
+        for motor in [Motor::M2, Motor::Both] {
+            assert!(matches!(
+                roboclaw.send(motor, Duty { duty: 0 }),
+                Err(RoboClawError::UnsupportedByModel {
+                    model: Model::Solo { amps: 60 },
+                    ..
+                })
+            ));
+        }
+        handle.assert_finished();
+    }
+}
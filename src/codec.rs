@@ -0,0 +1,260 @@
+//! This module defines the typed wire codec used for RoboClaw packets.
+//!
+//! Every request field implements `Encode` and every response field implements
+//! `Decode`. Integers are sent big-endian with the width of their Rust type, so
+//! an `i16` duty cycle always takes two bytes and an `i32` speed four. Composite
+//! values are built from tuples and arrays of these, or from structs implementing
+//! the traits themselves.
+
+use crate::errors::ConnectionError;
+use std::convert::TryInto;
+
+/// Maximum length of a string response, such as the firmware version.
+pub const MAX_STRING_LEN: usize = 48;
+
+/// Source of response bytes for `Decode`.
+pub trait WireRead {
+    /// Fills `buffer` with the next bytes of the response.
+    fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), ConnectionError>;
+}
+
+/// A value that can be written into a request packet.
+pub trait Encode {
+    /// Appends the wire representation of `self` to `buffer`.
+    fn encode(&self, buffer: &mut Vec<u8>);
+}
+
+/// A value that can be read from a response packet.
+pub trait Decode: Sized {
+    /// Reads a value from `reader`.
+    fn decode<R: WireRead + ?Sized>(reader: &mut R) -> Result<Self, ConnectionError>;
+
+    /// Returns the number of bytes a value takes on the wire, or `None` if the size
+    /// is only known while reading, as for strings.
+    fn wire_size() -> Option<usize>;
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        (**self).encode(buffer)
+    }
+}
+
+macro_rules! impl_integer {
+    ($($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, buffer: &mut Vec<u8>) {
+                    buffer.extend_from_slice(&self.to_be_bytes());
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode<R: WireRead + ?Sized>(reader: &mut R) -> Result<Self, ConnectionError> {
+                    let mut bytes = [0u8; std::mem::size_of::<$ty>()];
+                    reader.read_bytes(&mut bytes)?;
+                    Ok(<$ty>::from_be_bytes(bytes))
+                }
+
+                fn wire_size() -> Option<usize> {
+                    Some(std::mem::size_of::<$ty>())
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(u8, u16, u32, i8, i16, i32);
+
+impl Encode for bool {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        (*self as u8).encode(buffer)
+    }
+}
+
+impl Decode for bool {
+    fn decode<R: WireRead + ?Sized>(reader: &mut R) -> Result<Self, ConnectionError> {
+        Ok(u8::decode(reader)? != 0)
+    }
+
+    fn wire_size() -> Option<usize> {
+        Some(1)
+    }
+}
+
+impl Encode for str {
+    /// Strings are sent NUL-terminated.
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(self.as_bytes());
+        buffer.push(0);
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.as_str().encode(buffer)
+    }
+}
+
+impl Decode for String {
+    /// Reads a NUL-terminated string of at most `MAX_STRING_LEN` bytes. The
+    /// terminator is not part of the result.
+    fn decode<R: WireRead + ?Sized>(reader: &mut R) -> Result<Self, ConnectionError> {
+        let mut data = Vec::new();
+        while data.len() < MAX_STRING_LEN {
+            match u8::decode(reader)? {
+                0 => break,
+                byte => data.push(byte),
+            }
+        }
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    fn wire_size() -> Option<usize> {
+        None
+    }
+}
+
+impl Encode for () {
+    fn encode(&self, _buffer: &mut Vec<u8>) {}
+}
+
+impl Decode for () {
+    fn decode<R: WireRead + ?Sized>(_reader: &mut R) -> Result<Self, ConnectionError> {
+        Ok(())
+    }
+
+    fn wire_size() -> Option<usize> {
+        Some(0)
+    }
+}
+
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        for value in self {
+            value.encode(buffer);
+        }
+    }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode<R: WireRead + ?Sized>(reader: &mut R) -> Result<Self, ConnectionError> {
+        let mut values = Vec::with_capacity(N);
+        for _ in 0..N {
+            values.push(T::decode(reader)?);
+        }
+        match values.try_into() {
+            Ok(values) => Ok(values),
+            Err(_) => unreachable!("exactly N values were decoded"),
+        }
+    }
+
+    fn wire_size() -> Option<usize> {
+        T::wire_size().map(|size| size * N)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: Encode),+> Encode for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode(&self, buffer: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode(buffer);)+
+            }
+        }
+
+        impl<$($name: Decode),+> Decode for ($($name,)+) {
+            fn decode<R: WireRead + ?Sized>(reader: &mut R) -> Result<Self, ConnectionError> {
+                Ok(($($name::decode(reader)?,)+))
+            }
+
+            fn wire_size() -> Option<usize> {
+                Some(0 $(+ $name::wire_size()?)+)
+            }
+        }
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
+impl_tuple!(A, B, C, D, E, F);
+impl_tuple!(A, B, C, D, E, F, G);
+impl_tuple!(A, B, C, D, E, F, G, H);
+impl_tuple!(A, B, C, D, E, F, G, H, I);
+impl_tuple!(A, B, C, D, E, F, G, H, I, J);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads from a byte slice, failing like a timeout once it is exhausted.
+    struct Bytes<'a>(&'a [u8]);
+
+    impl WireRead for Bytes<'_> {
+        fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), ConnectionError> {
+            if self.0.len() < buffer.len() {
+                return Err(ConnectionError::NoResponse);
+            }
+            let (head, tail) = self.0.split_at(buffer.len());
+            buffer.copy_from_slice(head);
+            self.0 = tail;
+            Ok(())
+        }
+    }
+
+    fn encoded<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        value.encode(&mut buffer);
+        buffer
+    }
+
+    fn decoded<T: Decode>(bytes: &[u8]) -> Result<T, ConnectionError> {
+        T::decode(&mut Bytes(bytes))
+    }
+
+    #[test]
+    fn integers_are_big_endian_with_their_own_width() {
+        assert_eq!(encoded(&0x12u8), [0x12]);
+        assert_eq!(encoded(&-2i16), [0xFF, 0xFE]);
+        assert_eq!(encoded(&0x0102_0304u32), [1, 2, 3, 4]);
+        assert_eq!(encoded(&-1i32), [0xFF; 4]);
+        assert_eq!(decoded::<i16>(&[0x80, 0x00]).unwrap(), i16::MIN);
+        assert_eq!(decoded::<u32>(&[0, 0, 0x01, 0x00]).unwrap(), 256);
+        assert_eq!(decoded::<i8>(&[0xFF]).unwrap(), -1);
+    }
+
+    #[test]
+    fn composites_concatenate_their_fields() {
+        let value = (1000i32, 5000u32, true);
+        let bytes = encoded(&value);
+        assert_eq!(bytes, [0, 0, 0x03, 0xE8, 0, 0, 0x13, 0x88, 1]);
+        assert_eq!(<(i32, u32, bool)>::wire_size(), Some(9));
+        assert_eq!(decoded::<(i32, u32, bool)>(&bytes).unwrap(), value);
+
+        assert_eq!(encoded(&[1u16, 2u16]), [0, 1, 0, 2]);
+        assert_eq!(<[u16; 2]>::wire_size(), Some(4));
+        assert_eq!(decoded::<[i16; 2]>(&[0xFF, 0xFF, 0, 3]).unwrap(), [-1, 3]);
+        assert_eq!(encoded(&()), [] as [u8; 0]);
+        assert_eq!(<(u8, String)>::wire_size(), None);
+    }
+
+    #[test]
+    fn strings_are_nul_terminated() {
+        assert_eq!(encoded("v4"), b"v4\0");
+        assert_eq!(decoded::<String>(b"v4.1.34\0rest").unwrap(), "v4.1.34");
+
+        let long = [b'a'; MAX_STRING_LEN + 10];
+        assert_eq!(decoded::<String>(&long).unwrap().len(), MAX_STRING_LEN);
+    }
+
+    #[test]
+    fn short_input_fails() {
+        assert!(decoded::<u32>(&[1, 2, 3]).is_err());
+        assert!(decoded::<[u8; 2]>(&[1]).is_err());
+        assert!(decoded::<String>(b"no terminator").is_err());
+    }
+}
@@ -4,12 +4,25 @@
 //! management. The commands are encoded as `u8` values for efficient transmission over a serial
 //! communication protocol.
 //!
+//! Some commands were only added in later firmware releases, `Commands::min_firmware`
+//! tells which version is needed to use them. `Commands::is_idempotent` tells whether a
+//! command can safely be resent when its acknowledgement is lost. Both are read from
+//! the command's entry in the `schema` module.
+//!
+//! Commands 84–89, 96–97, 125–132 and 137–147 of newer firmware (position and speed
+//! limits, S4/S5 modes, inductance and resistance, motor priority and others) are not
+//! defined, as neither their argument layouts nor the firmware version that added
+//! them have been verified against the manual or a controller. The extended status
+//! is not a separate command: `ReadStatus` returns the 32-bit word on newer firmware.
+//!
 //! These commands are used internally to send instructions to the RoboClaw controller, and each
 //! variant in the `Commands` enum maps to a specific function or operation of the controller.
 
+use crate::firmware::FirmwareVersion;
+
 #[allow(dead_code)]
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Commands {
     M1Forward = 0,
     M1Backward = 1,
@@ -142,3 +155,22 @@ pub enum Commands {
     EEPROMReadUserMemoryLocation = 252,
     EEPROMWriteUserMemoryLocation = 253,
 }
+
+/// First firmware version assumed to report the 32-bit extended status word. Where
+/// the firmware string cannot be parsed, the status is decoded by its length.
+pub(crate) const EXTENDED_FIRMWARE: FirmwareVersion = FirmwareVersion::new(4, 2, 0);
+
+impl Commands {
+    /// Returns the minimum firmware version that supports this command, or `None`
+    /// if every firmware version supports it.
+    pub fn min_firmware(self) -> Option<FirmwareVersion> {
+        self.schema().min_firmware
+    }
+
+    /// Returns `true` if sending this command twice has the same effect as sending it
+    /// once. Buffered drive commands are not idempotent: a resent packet queues the
+    /// move a second time.
+    pub fn is_idempotent(self) -> bool {
+        self.schema().idempotent
+    }
+}
@@ -1,40 +1,81 @@
 //! This module handles the serial connection between the controller and
 //! the RoboClaw.
 
-use crate::{errors::ConnectionError, Commands};
+use crate::{
+    builder::Timeouts,
+    codec::{Decode, Encode, WireRead},
+    errors::{CommandError, ConnectionError},
+    retry::RetryPolicy,
+    schema::Reply,
+    stats::Stats,
+    trace::{Direction, Frame, TraceSink},
+    transport::Transport,
+    Commands,
+};
 use crc16::{State, XMODEM};
-use serialport::{ClearBuffer, SerialPort};
-use std::time::Duration;
+use std::{
+    io::ErrorKind,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 
 /// Represents the serial connection to the RoboClaw motor controller.
 pub(crate) struct Connection {
-    port: Box<dyn SerialPort>, // The serial port for commjnication
-    pub(crate) address: u8,    // The address of the RoboClaw device
-    tries: u8,                 // Number of attempts to retry a failed operation
-    crc: State<XMODEM>,        // CRC16 XMODEM state for the checksum calculation
-    buffer: Vec<u8>,           // Buffer holding the data to be sent
+    port: Box<dyn Transport>, // The serial port or other transport for communication
+    pub(crate) address: u8,   // The address of the RoboClaw device
+    pub(crate) retry_policy: RetryPolicy, // Policy deciding how failed operations are retried
+    pub(crate) timeouts: Timeouts, // Response timeouts per command class
+    crc: State<XMODEM>,       // CRC16 XMODEM state for the checksum calculation
+    buffer: Vec<u8>,          // Buffer holding the data to be sent
+    expected: usize,          // Number of response bytes expected in the current attempt, if known
+    received: usize,          // Number of response bytes received in the current attempt
+    trace: Option<Box<dyn TraceSink>>, // Receives every frame sent and received, if set
+    response: Vec<u8>,        // Response bytes of the current attempt, kept while tracing
+    pub(crate) stats: Stats,  // Counters and latencies of the commands sent so far
+    sent: Option<Instant>,    // When the request of the current attempt was written
+    pub(crate) exchanges: Option<(Instant, Instant)>, // First request sent and last response received, see `Roboclaw::timestamped`
+    pub(crate) preempt: Option<Arc<AtomicUsize>>, // Number of priority commands waiting, set by `SharedRoboclaw`
 }
 
 impl Connection {
     /// Creates a new `Connection` instance with the specified parameters.
     /// Initializes the CRC State and prepares the buffer for communication.
     pub(crate) fn new(
-        mut port: Box<dyn SerialPort>,
+        mut port: Box<dyn Transport>,
         address: u8,
-        tries: u8,
+        retry_policy: RetryPolicy,
     ) -> Result<Self, ConnectionError> {
-        port.set_timeout(Duration::from_millis(5))?; // making sure that the timeout is under 10 milliseconds
+        let timeouts = Timeouts::default();
+        port.set_timeout(timeouts.read)?;
         let crc = State::<XMODEM>::new();
         let buffer = Vec::new();
         Ok(Connection {
             port,
             address,
-            tries,
+            retry_policy,
+            timeouts,
             crc,
             buffer,
+            expected: 0,
+            received: 0,
+            trace: None,
+            response: Vec::new(),
+            stats: Stats::default(),
+            sent: None,
+            exchanges: None,
+            preempt: None,
         })
     }
 
+    /// Sets the sink receiving every frame, or disables tracing with `None`.
+    pub(crate) fn set_trace(&mut self, trace: Option<Box<dyn TraceSink>>) {
+        self.trace = trace;
+    }
+
     /// Initializes a new CRC16 XMODEM state.
     fn initialize_crc(&self) -> State<XMODEM> {
         State::<XMODEM>::new()
@@ -42,115 +83,522 @@ impl Connection {
 
     /// Resets the connection by clearing the buffer and CRC state.
     fn reset_connection(&mut self) -> Result<(), ConnectionError> {
-        self.port.clear(ClearBuffer::All)?;
+        self.port.clear()?;
         self.crc = self.initialize_crc();
         self.buffer.clear();
+        self.expected = 0;
+        self.received = 0;
+        self.response.clear();
+        Ok(())
+    }
+
+    /// Writes the packet in the buffer to the serial port and traces it.
+    fn transmit(&mut self, command: Commands) -> Result<(), ConnectionError> {
+        self.port.write_all(&self.buffer)?;
+        self.sent = Some(Instant::now());
+        if self.trace.is_some() {
+            let bytes = self.buffer.clone();
+            self.record(Direction::Tx, command, bytes);
+        }
         Ok(())
     }
 
+    /// Runs a single exchange with the RoboClaw and traces the response bytes it
+    /// received, whether or not the exchange succeeded. A successful exchange
+    /// extends the time span in `exchanges`.
+    fn traced<T>(
+        &mut self,
+        command: Commands,
+        exchange: impl FnOnce(&mut Self) -> Result<T, ConnectionError>,
+    ) -> Result<T, ConnectionError> {
+        self.sent = None;
+        let result = exchange(self);
+        if let (Ok(_), Some(sent)) = (&result, self.sent) {
+            let first = self.exchanges.map_or(sent, |(first, _)| first);
+            self.exchanges = Some((first, Instant::now()));
+        }
+        if self.trace.is_some() && !self.response.is_empty() {
+            let bytes = std::mem::take(&mut self.response);
+            self.record(Direction::Rx, command, bytes);
+        }
+        result
+    }
+
+    /// Passes a frame to the trace sink.
+    fn record(&mut self, direction: Direction, command: Commands, bytes: Vec<u8>) {
+        let frame = Frame {
+            timestamp: SystemTime::now(),
+            direction,
+            address: self.address,
+            command,
+            bytes,
+        };
+        if let Some(trace) = self.trace.as_mut() {
+            trace.record(&frame);
+        }
+    }
+
     /// Sends the address and command to the motor controller, updating the CRC
     fn send_command(&mut self, command: Commands) {
         self.crc.update(&[self.address, command as u8]);
         self.buffer.extend(&[self.address, command as u8]);
     }
 
-    /// Writes the specified command and values to the RoboClaw.
-    /// Attempts multiple retries on failure. Returns `true` if successful.
-    pub(crate) fn write<const N: usize>(
+    /// Appends the encoded arguments of the command to the buffer, updating the CRC.
+    fn encode_args<T: Encode + ?Sized>(&mut self, command: Commands, args: &T) {
+        let start = self.buffer.len();
+        args.encode(&mut self.buffer);
+        debug_assert_eq!(
+            command.schema().request_size(),
+            Some(self.buffer.len() - start),
+            "request of {:?} does not match its schema",
+            command
+        );
+        self.crc.update(&self.buffer[start..]);
+    }
+
+    /// Waits for `delay` and drains any bytes still arriving from an earlier attempt,
+    /// so that the next attempt does not read a stale response.
+    fn resync(&mut self, delay: Duration) -> Result<(), ConnectionError> {
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+        let mut stray = [0u8; 64];
+        loop {
+            let pending = self.port.bytes_to_read()? as usize;
+            if pending == 0 {
+                break;
+            }
+            let count = pending.min(stray.len());
+            match self.port.read(&mut stray[..count]) {
+                Ok(0) => break,
+                Ok(_) => continue,
+                Err(error) if error.kind() == ErrorKind::TimedOut => break,
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `attempt` until it succeeds or the retry policy gives up, resynchronizing
+    /// with the RoboClaw between attempts. The returned error holds the error of
+    /// every attempt.
+    fn transact<T>(
         &mut self,
         command: Commands,
-        values: &[u32; N],
-        byte_sizes: &[u8; N],
-    ) -> Result<bool, ConnectionError> {
-        for _ in 0..self.tries {
-            self.reset_connection()?;
-            self.send_command(command);
-
-            for (i, &byte_size) in byte_sizes.iter().enumerate() {
-                let val = values[i];
-                match byte_size {
-                    1 => {
-                        self.crc.update(&[val as u8]);
-                        self.buffer.extend_from_slice(&[val as u8]);
-                    }
-                    2 => {
-                        self.crc.update(&(val as u16).to_be_bytes());
-                        self.buffer.extend_from_slice(&(val as u16).to_be_bytes());
-                    }
-                    4 => {
-                        self.crc.update(&val.to_be_bytes());
-                        self.buffer.extend_from_slice(&val.to_be_bytes());
-                    }
-                    _ => return Err(ConnectionError::InvalidByteSize(byte_size)),
+        mut attempt: impl FnMut(&mut Self) -> Result<T, ConnectionError>,
+    ) -> Result<T, CommandError> {
+        let policy = self.retry_policy.clone();
+        let mut history = Vec::new();
+        let mut result = self
+            .port
+            .set_timeout(
+                policy
+                    .get_timeout()
+                    .unwrap_or_else(|| self.timeouts.get(command.schema().class())),
+            )
+            .map_err(ConnectionError::from);
+        loop {
+            let started = Instant::now();
+            let outcome = result.and_then(|_| match self.port.ensure_connected() {
+                true => attempt(self),
+                false => Err(ConnectionError::Disconnected),
+            });
+            let error = match outcome {
+                Ok(value) => {
+                    self.stats
+                        .record(command, &history, Some(started.elapsed()));
+                    return Ok(value);
                 }
+                Err(error) => error,
+            };
+            let retry = policy.should_retry(&error, history.len() + 1)
+                && !(command.schema().motion && matches!(error, ConnectionError::Disconnected))
+                && !self.preempted();
+            if !retry {
+                let error = CommandError::new(command, self.address, history, error);
+                self.stats.record(command, error.history(), None);
+                return Err(error);
             }
+            history.push(error);
+            result = self.resync(policy.get_backoff().delay(history.len()));
+        }
+    }
 
-            let crc_bytes = self.crc.get().to_be_bytes();
-            self.buffer.extend_from_slice(&crc_bytes);
+    /// Returns `true` if a priority command is waiting for this connection, in
+    /// which case the current command is not retried.
+    fn preempted(&self) -> bool {
+        self.preempt
+            .as_ref()
+            .is_some_and(|waiting| waiting.load(Ordering::Acquire) > 0)
+    }
 
-            self.port.write_all(&self.buffer)?;
+    /// Writes the specified command and values to the RoboClaw.
+    /// Attempts multiple retries on failure. Returns `true` if successful.
+    ///
+    /// Commands that are not idempotent (buffered drive commands) are only resent
+    /// if the motor buffers show that the lost attempt was not queued. This costs
+    /// one buffer read before the command is sent.
+    pub(crate) fn write<T: Encode + ?Sized>(
+        &mut self,
+        command: Commands,
+        values: &T,
+    ) -> Result<bool, CommandError> {
+        debug_assert!(
+            command.schema().reply == Reply::Ack,
+            "{:?} is answered with data, not an acknowledgement",
+            command
+        );
+        if command.is_idempotent() {
+            self.transact(command, |connection| {
+                connection.write_attempt(command, values)
+            })?;
+            return Ok(true);
+        }
 
-            let mut ack = [0u8; 1];
-            self.port.read_exact(&mut ack)?;
-            let success: bool = match ack[0] {
-                0xFF => true,
-                _ => false,
-            };
-            if success {
-                return Ok(true);
+        let mut before: [u8; 2] = self.read(Commands::ReadBufferLength)?;
+        self.transact(command, |connection| {
+            match connection.write_attempt(command, values) {
+                Ok(()) => Ok(()),
+                Err(error) => connection.verify_delivery(command, &mut before, error),
+            }
+        })?;
+        Ok(true)
+    }
+
+    /// Decides whether a non-idempotent command whose attempt failed with `error` was
+    /// queued anyway, by comparing the motor buffers against their state `before`
+    /// the command was sent. Returns `Ok` if a buffer it is queued on grew, `error` if
+    /// those buffers are empty so it may be resent, and `Unverified` otherwise: a
+    /// buffer can also shrink or stay the same while the command was queued, as
+    /// earlier moves finish or `execute_directly` replaces them. On `error`,
+    /// `before` is updated to the empty buffers the resent command will be
+    /// compared against.
+    fn verify_delivery(
+        &mut self,
+        command: Commands,
+        before: &mut [u8; 2],
+        error: ConnectionError,
+    ) -> Result<(), ConnectionError> {
+        let mut after = None;
+        for failures in 0..self.retry_policy.get_max_attempts() as usize {
+            let delay = self.retry_policy.get_backoff().delay(failures);
+            if failures > 0 && self.resync(delay).is_err() {
+                break;
+            }
+            if let Ok(buffers) = self.read_attempt::<_, [u8; 2]>(Commands::ReadBufferLength, &()) {
+                after = Some(buffers);
+                break;
             }
         }
+        let after = match after {
+            Some(after) => after,
+            None => return Err(ConnectionError::Unverified),
+        };
 
-        Err(ConnectionError::CRCMismatch)
+        let motors = buffered_motors(command);
+        let grew = (0..2).any(|i| motors[i] && queued(after[i]) > queued(before[i]));
+        let empty = (0..2).all(|i| !motors[i] || after[i] == BUFFER_EMPTY);
+        if grew {
+            Ok(())
+        } else if empty {
+            *before = after;
+            Err(error)
+        } else {
+            Err(ConnectionError::Unverified)
+        }
     }
 
-    /// Reads data from the RoboClaw based on the provided command and expected sizes.
-    /// Returns an array of values read from the device.
-    pub(crate) fn read<const N: usize>(
+    /// Sends the command and values once and waits for the acknowledgement.
+    fn write_attempt<T: Encode + ?Sized>(
         &mut self,
         command: Commands,
-        byte_sizes: &[u8; N],
-    ) -> Result<[u32; N], ConnectionError> {
-        for _ in 0..self.tries {
-            self.reset_connection()?;
-            self.send_command(command);
-
-            let mut data = [0u32; N];
-            for (i, &byte_size) in byte_sizes.iter().enumerate() {
-                data[i] = match byte_size {
-                    1 => {
-                        let mut buffer = [0u8; 1];
-                        self.read_bytes(&mut buffer)?;
-                        buffer[0] as u32
-                    }
-                    2 => {
-                        let mut buffer = [0u8; 2];
-                        self.read_bytes(&mut buffer)?;
-                        u16::from_be_bytes(buffer) as u32
-                    }
-                    4 => {
-                        let mut buffer = [0u8; 4];
-                        self.read_bytes(&mut buffer)?;
-                        u32::from_be_bytes(buffer)
-                    }
-                    _ => return Err(ConnectionError::InvalidByteSize(byte_size)),
-                };
+        values: &T,
+    ) -> Result<(), ConnectionError> {
+        self.traced(command, |connection| {
+            connection.write_exchange(command, values)
+        })
+    }
+
+    /// Sends the packet of a write command and reads the acknowledgement.
+    fn write_exchange<T: Encode + ?Sized>(
+        &mut self,
+        command: Commands,
+        values: &T,
+    ) -> Result<(), ConnectionError> {
+        self.reset_connection()?;
+        self.send_command(command);
+
+        self.encode_args(command, values);
+
+        let crc_bytes = self.crc.get().to_be_bytes();
+        self.buffer.extend_from_slice(&crc_bytes);
+
+        self.transmit(command)?;
+
+        self.expected = 1;
+        let mut ack = [0u8; 1];
+        self.read_raw(&mut ack)?;
+        match ack[0] {
+            0xFF => Ok(()),
+            ack => Err(ConnectionError::BadAck(ack)),
+        }
+    }
+
+    /// Reads data from the RoboClaw based on the provided command, decoding the
+    /// response as `T`.
+    pub(crate) fn read<T: Decode>(&mut self, command: Commands) -> Result<T, CommandError> {
+        self.query(command, &())
+    }
+
+    /// Reads data from the RoboClaw for a read command taking arguments, such as a
+    /// user EEPROM location, decoding the response as `T`.
+    pub(crate) fn query<A: Encode + ?Sized, T: Decode>(
+        &mut self,
+        command: Commands,
+        args: &A,
+    ) -> Result<T, CommandError> {
+        debug_assert!(
+            command.schema().reply != Reply::Ack
+                && command.schema().accepts_reply_size(T::wire_size()),
+            "reply type of {:?} does not match its schema",
+            command
+        );
+        self.transact(command, |connection| connection.read_attempt(command, args))
+    }
+
+    /// Requests the command once and reads back the values and CRC.
+    fn read_attempt<A: Encode + ?Sized, T: Decode>(
+        &mut self,
+        command: Commands,
+        args: &A,
+    ) -> Result<T, ConnectionError> {
+        self.traced(command, |connection| {
+            connection.read_exchange(command, args)
+        })
+    }
+
+    /// Sends the request of a read command and reads the response. The CRC of the
+    /// response covers the request arguments as well.
+    fn read_exchange<A: Encode + ?Sized, T: Decode>(
+        &mut self,
+        command: Commands,
+        args: &A,
+    ) -> Result<T, ConnectionError> {
+        self.reset_connection()?;
+        self.send_command(command);
+        self.encode_args(command, args);
+        self.transmit(command)?;
+
+        self.expected = T::wire_size().map_or(0, |size| size + 2);
+        let data = T::decode(self)?;
+        self.read_crc()?;
+        Ok(data)
+    }
+
+    /// Reads a response of `short` or `long` data bytes, for a read command whose
+    /// reply grew in newer firmware. The length is taken from the response itself:
+    /// one that stops after `short` bytes and its CRC is accepted once the read
+    /// timeout expires, so a short reply costs one read timeout.
+    pub(crate) fn read_sized(
+        &mut self,
+        command: Commands,
+        short: usize,
+        long: usize,
+    ) -> Result<Vec<u8>, CommandError> {
+        self.transact(command, |connection| {
+            connection.traced(command, |connection| {
+                connection.read_sized_exchange(command, short, long)
+            })
+        })
+    }
+
+    /// Sends the request of a read command and reads a response of `short` or
+    /// `long` data bytes, see `read_sized`.
+    fn read_sized_exchange(
+        &mut self,
+        command: Commands,
+        short: usize,
+        long: usize,
+    ) -> Result<Vec<u8>, ConnectionError> {
+        self.reset_connection()?;
+        self.send_command(command);
+        self.transmit(command)?;
+
+        self.expected = long + 2;
+        let mut response = vec![0u8; long + 2];
+        match self.read_raw(&mut response) {
+            Ok(()) => {}
+            Err(ConnectionError::ShortResponse { received, .. }) if received == short + 2 => {
+                response.truncate(short + 2)
             }
+            Err(error) => return Err(error),
+        }
+        let crc = response.split_off(response.len() - 2);
+        self.crc.update(&response);
+        self.check_crc(u16::from_be_bytes([crc[0], crc[1]]))?;
+        Ok(response)
+    }
+
+    /// Reads the trailing CRC of a response and compares it against the CRC state.
+    fn read_crc(&mut self) -> Result<(), ConnectionError> {
+        let mut crc = [0u8; 2];
+        self.read_raw(&mut crc)?;
+        self.check_crc(u16::from_be_bytes(crc))
+    }
 
-            let mut crc = [0u8; 2];
-            let _ = self.port.read_exact(&mut crc);
-            if self.crc.get().to_be_bytes() == crc {
-                return Ok(data);
+    /// Compares the received CRC `actual` against the CRC state.
+    fn check_crc(&self, actual: u16) -> Result<(), ConnectionError> {
+        let expected = self.crc.get();
+        if expected == actual {
+            Ok(())
+        } else {
+            Err(ConnectionError::CRCMismatch { expected, actual })
+        }
+    }
+
+    /// Fills `buffer` from the serialport. A timeout is reported as `NoResponse` if
+    /// nothing at all was received in this attempt and as `ShortResponse` otherwise.
+    fn read_raw(&mut self, buffer: &mut [u8]) -> Result<(), ConnectionError> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match self.port.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(count) => filled += count,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) if error.kind() == ErrorKind::TimedOut => break,
+                Err(error) => return Err(error.into()),
             }
         }
+        self.received += filled;
+        if self.trace.is_some() {
+            self.response.extend_from_slice(&buffer[..filled]);
+        }
+        if filled == buffer.len() {
+            return Ok(());
+        }
 
-        Err(ConnectionError::CRCMismatch)
+        if self.received == 0 {
+            Err(ConnectionError::NoResponse)
+        } else {
+            let expected = self.received - filled + buffer.len();
+            Err(ConnectionError::ShortResponse {
+                expected: self.expected.max(expected),
+                received: self.received,
+            })
+        }
     }
+}
 
+impl WireRead for Connection {
     /// Reads a specififc number of bytes from the serialport and updates the CRC state.
     fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), ConnectionError> {
-        self.port.read_exact(buffer)?;
-        self.crc.update(&buffer);
+        self.read_raw(buffer)?;
+        self.crc.update(buffer);
         Ok(())
     }
 }
+
+/// Value reported by `ReadBufferLength` for a motor without buffered or running commands.
+const BUFFER_EMPTY: u8 = 0x80;
+
+/// Returns the number of commands a motor is running or has buffered, from its
+/// `ReadBufferLength` value.
+fn queued(length: u8) -> u16 {
+    match length {
+        BUFFER_EMPTY => 0,
+        length => length as u16 + 1,
+    }
+}
+
+/// Returns which motor buffers, `[M1, M2]`, a buffered command is queued on.
+fn buffered_motors(command: Commands) -> [bool; 2] {
+    match command {
+        Commands::M1DriveSignedSpeedDistanceBuffered
+        | Commands::M1DriveSignedSpeedAccelDistanceBuffered
+        | Commands::M1DriveSpeedAccelDeccelPosition
+        | Commands::M1DrivePosition
+        | Commands::M1DriveSpeedPosition => [true, false],
+        Commands::M2DriveSignedSpeedDistanceBuffered
+        | Commands::M2DriveSignedSpeedAccelDistanceBuffered
+        | Commands::M2DriveSpeedAccelDeccelPosition
+        | Commands::M2DrivePosition
+        | Commands::M2DriveSpeedPosition => [false, true],
+        _ => [true, true],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        replay::{
+            tests::{ack, read, write, ADDRESS},
+            ReplayHandle, ReplayTransport,
+        },
+        retry::Backoff,
+        trace::Frame,
+    };
+
+    const MOVE: Commands = Commands::M1DriveSignedSpeedDistanceBuffered;
+    const MOVE_ARGS: (i32, u32, u8) = (1000, 5000, 0);
+    const MOVE_BYTES: [u8; 9] = [0, 0, 0x03, 0xE8, 0, 0, 0x13, 0x88, 0];
+
+    fn connect(frames: Vec<Frame>) -> (Connection, ReplayHandle) {
+        let transport = ReplayTransport::from_frames(frames);
+        let handle = transport.handle();
+        let policy = RetryPolicy::new().backoff(Backoff::None);
+        let connection = Connection::new(Box::new(transport), ADDRESS, policy).unwrap();
+        (connection, handle)
+    }
+
+    fn buffers(m1: u8, m2: u8) -> [Frame; 2] {
+        read(Commands::ReadBufferLength, &[m1, m2])
+    }
+
+    #[test]
+    fn lost_ack_of_queued_move_is_not_resent() {
+        let mut frames = buffers(BUFFER_EMPTY, BUFFER_EMPTY).to_vec();
+        frames.push(write(MOVE, &MOVE_BYTES));
+        frames.extend(buffers(0, BUFFER_EMPTY));
+        let (mut connection, handle) = connect(frames);
+
+        assert!(connection.write(MOVE, &MOVE_ARGS).unwrap());
+        handle.assert_finished();
+    }
+
+    #[test]
+    fn lost_move_is_resent_when_an_earlier_move_finished() {
+        // The last move was executing before and finished while the packet was
+        // lost: the buffer changed, but the move was never queued.
+        let mut frames = buffers(0, BUFFER_EMPTY).to_vec();
+        frames.push(write(MOVE, &MOVE_BYTES));
+        frames.extend(buffers(BUFFER_EMPTY, BUFFER_EMPTY));
+        frames.push(write(MOVE, &MOVE_BYTES));
+        frames.push(ack(MOVE));
+        let (mut connection, handle) = connect(frames);
+
+        assert!(connection.write(MOVE, &MOVE_ARGS).unwrap());
+        handle.assert_finished();
+    }
+
+    #[test]
+    fn shrinking_buffer_is_unverified() {
+        let mut frames = buffers(2, BUFFER_EMPTY).to_vec();
+        frames.push(write(MOVE, &MOVE_BYTES));
+        frames.extend(buffers(1, BUFFER_EMPTY));
+        let (mut connection, handle) = connect(frames);
+
+        let error = connection.write(MOVE, &MOVE_ARGS).unwrap_err();
+        assert!(matches!(error.last_error(), ConnectionError::Unverified));
+        assert_eq!(error.attempts(), 1);
+        handle.assert_finished();
+    }
+
+    #[test]
+    fn queued_counts_the_running_command() {
+        assert_eq!(queued(BUFFER_EMPTY), 0);
+        assert_eq!(queued(0), 1);
+        assert_eq!(queued(3), 4);
+    }
+}
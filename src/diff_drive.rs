@@ -0,0 +1,327 @@
+//! This module provides kinematics and odometry for a differential-drive robot
+//! with the left wheel on M1 and the right wheel on M2.
+//!
+//! `DiffDrive` converts a linear and angular velocity into wheel speeds and
+//! integrates encoder readings into a `Pose` in the frame the robot started in:
+//! x forward, y to the left and the heading counter-clockwise from x.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), roboclaw::errors::RoboClawError> {
+//! use roboclaw::diff_drive::DiffDrive;
+//! use roboclaw::units::{Meters, MetersPerSecond, RadiansPerSecond, Wheel};
+//! use roboclaw::Roboclaw;
+//!
+//! let mut roboclaw = Roboclaw::builder().port("/dev/ttyACM0").build()?;
+//! let wheel = Wheel::new(2048.0, 19.2, Meters(0.05));
+//! let mut drive = DiffDrive::new(Meters(0.3), wheel);
+//! drive.drive(&mut roboclaw, MetersPerSecond(0.5), RadiansPerSecond(0.2))?;
+//! loop {
+//!     let pose = drive.update(&mut roboclaw)?;
+//!     println!("{} {} {}", pose.x, pose.y, pose.heading);
+//! }
+//! # }
+//! ```
+//!
+//! Encoder readings can also come from a telemetry poller: pass
+//! `Snapshot::encoders` to `DiffDrive::integrate`.
+
+use crate::{
+    errors::RoboClawError,
+    timestamp::Timestamped,
+    units::{Meters, MetersPerSecond, Radians, RadiansPerSecond, Wheel},
+    Roboclaw,
+};
+use std::{f64::consts::PI, time::Instant};
+
+/// The position and heading of the robot.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pose {
+    pub x: Meters,        // Distance forward from the origin
+    pub y: Meters,        // Distance to the left of the origin
+    pub heading: Radians, // Counter-clockwise from the x axis, within -π..=π
+}
+
+/// The velocity of the robot in its own frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Velocity {
+    pub linear: MetersPerSecond,   // Forward speed
+    pub angular: RadiansPerSecond, // Counter-clockwise turn rate
+}
+
+/// A differential-drive robot, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct DiffDrive {
+    wheel_base: Meters,                // Distance between the wheel contact points
+    wheels: [Wheel; 2],                // The left (M1) and right (M2) wheel
+    pose: Pose,                        // The integrated pose
+    velocity: Velocity,                // The velocity over the last update
+    last: Option<([u32; 2], Instant)>, // Counts and sample time of the last reading
+}
+
+impl DiffDrive {
+    /// Describes a robot with `wheel_base` between two identical wheels.
+    pub fn new(wheel_base: Meters, wheel: Wheel) -> Self {
+        DiffDrive::with_wheels(wheel_base, wheel, wheel)
+    }
+
+    /// Describes a robot with `wheel_base` between `left` on M1 and `right` on M2.
+    pub fn with_wheels(wheel_base: Meters, left: Wheel, right: Wheel) -> Self {
+        DiffDrive {
+            wheel_base,
+            wheels: [left, right],
+            pose: Pose::default(),
+            velocity: Velocity::default(),
+            last: None,
+        }
+    }
+
+    /// Returns the left and right wheel speeds for `linear` and `angular` velocity.
+    pub fn wheel_speeds(
+        &self,
+        linear: MetersPerSecond,
+        angular: RadiansPerSecond,
+    ) -> [MetersPerSecond; 2] {
+        let turn = MetersPerSecond(angular.0 * self.wheel_base.0 / 2.0);
+        [linear - turn, linear + turn]
+    }
+
+    /// Returns the velocity of the robot for the left and right wheel speeds.
+    pub fn velocity_of(&self, left: MetersPerSecond, right: MetersPerSecond) -> Velocity {
+        Velocity {
+            linear: (left + right) / 2.0,
+            angular: RadiansPerSecond((right.0 - left.0) / self.wheel_base.0),
+        }
+    }
+
+    /// Drives at `linear` and `angular` velocity with the speed PID of both motors.
+    pub fn drive(
+        &self,
+        roboclaw: &mut Roboclaw,
+        linear: MetersPerSecond,
+        angular: RadiansPerSecond,
+    ) -> Result<bool, RoboClawError> {
+        let [left, right] = self.wheel_speeds(linear, angular);
+        roboclaw.units(self.wheels).set_speeds(left, right)
+    }
+
+    /// Stops both wheels with the speed PID.
+    pub fn stop(&self, roboclaw: &mut Roboclaw) -> Result<bool, RoboClawError> {
+        self.drive(roboclaw, MetersPerSecond(0.0), RadiansPerSecond(0.0))
+    }
+
+    /// Reads the encoders and integrates them into the pose, see `integrate`.
+    pub fn update(&mut self, roboclaw: &mut Roboclaw) -> Result<Pose, RoboClawError> {
+        let encoders = roboclaw.timestamped(Roboclaw::read_encoders)?;
+        Ok(self.integrate(encoders))
+    }
+
+    /// Integrates the encoder counts of M1 and M2 into the pose and returns it. The
+    /// first reading only sets the reference counts. Readings not newer than the
+    /// last one are ignored.
+    pub fn integrate(&mut self, encoders: Timestamped<[u32; 2]>) -> Pose {
+        let (counts, time) = (encoders.value, encoders.midpoint());
+        let (last, last_time) = match self.last {
+            Some((_, last_time)) if time <= last_time => return self.pose,
+            Some(last) => last,
+            None => {
+                self.last = Some((counts, time));
+                return self.pose;
+            }
+        };
+        self.last = Some((counts, time));
+
+        // Wrapping subtraction keeps deltas right across the counter rolling over.
+        let [left, right] = [0, 1].map(|i| {
+            let delta = counts[i].wrapping_sub(last[i]) as i32;
+            self.wheels[i].distance(delta.into())
+        });
+        let distance = (left + right) / 2.0;
+        let turn = (right.0 - left.0) / self.wheel_base.0;
+
+        // Advance along the mean heading of the step.
+        let heading = self.pose.heading.0 + turn / 2.0;
+        self.pose.x.0 += distance.0 * heading.cos();
+        self.pose.y.0 += distance.0 * heading.sin();
+        self.pose.heading = Radians(normalize(self.pose.heading.0 + turn));
+
+        let seconds = (time - last_time).as_secs_f64();
+        self.velocity = Velocity {
+            linear: MetersPerSecond(distance.0 / seconds),
+            angular: RadiansPerSecond(turn / seconds),
+        };
+        self.pose
+    }
+
+    /// Returns the integrated pose.
+    pub fn pose(&self) -> Pose {
+        self.pose
+    }
+
+    /// Returns the velocity over the last integrated step.
+    pub fn velocity(&self) -> Velocity {
+        self.velocity
+    }
+
+    /// Replaces the integrated pose, e.g. to correct it from another sensor.
+    pub fn set_pose(&mut self, pose: Pose) {
+        self.pose = Pose {
+            heading: Radians(normalize(pose.heading.0)),
+            ..pose
+        };
+    }
+
+    /// Resets the pose to the origin and forgets the reference counts, so the next
+    /// reading starts integrating afresh.
+    pub fn reset(&mut self) {
+        self.pose = Pose::default();
+        self.velocity = Velocity::default();
+        self.last = None;
+    }
+}
+
+/// Wraps `angle` into -π..=π.
+fn normalize(angle: f64) -> f64 {
+    let angle = (angle + PI).rem_euclid(2.0 * PI) - PI;
+    if angle == -PI {
+        PI
+    } else {
+        angle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A robot with 1000 counts per metre on each wheel and half a metre between
+    /// them.
+    fn drive() -> DiffDrive {
+        DiffDrive::new(Meters(0.5), Wheel::new(1000.0, 1.0, Meters(0.5 / PI)))
+    }
+
+    /// Encoder counts sampled `millis` after `start`.
+    fn reading(start: Instant, millis: u64, counts: [u32; 2]) -> Timestamped<[u32; 2]> {
+        let time = start + Duration::from_millis(millis);
+        Timestamped {
+            value: counts,
+            sent: time,
+            received: time,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn first_reading_only_sets_the_reference() {
+        let mut drive = drive();
+        let start = Instant::now();
+        assert_eq!(
+            drive.integrate(reading(start, 0, [5000, 7000])),
+            Pose::default()
+        );
+        drive.integrate(reading(start, 100, [5100, 7100]));
+        assert_close(drive.pose().x.0, 0.1);
+        assert_close(drive.velocity().linear.0, 1.0);
+    }
+
+    #[test]
+    fn straight_line_across_the_counter_wrap() {
+        let mut drive = drive();
+        let start = Instant::now();
+        let before = u32::MAX - 499;
+        drive.integrate(reading(start, 0, [before, before]));
+        let pose = drive.integrate(reading(start, 500, [500, 500]));
+        assert_close(pose.x.0, 1.0);
+        assert_close(pose.y.0, 0.0);
+        assert_close(pose.heading.0, 0.0);
+        assert_close(drive.velocity().linear.0, 2.0);
+
+        // Driving backwards wraps the other way.
+        let pose = drive.integrate(reading(start, 1000, [u32::MAX - 499, u32::MAX - 499]));
+        assert_close(pose.x.0, 0.0);
+    }
+
+    #[test]
+    fn turning_in_place_only_changes_the_heading() {
+        let mut drive = drive();
+        let start = Instant::now();
+        drive.integrate(reading(start, 0, [0, 0]));
+        // A quarter turn moves each wheel a quarter of the π/2 m circle.
+        let quarter = (PI / 8.0 * 1000.0).round() as u32;
+        let pose = drive.integrate(reading(start, 1000, [0u32.wrapping_sub(quarter), quarter]));
+        assert_close(pose.x.0, 0.0);
+        assert_close(pose.y.0, 0.0);
+        // Each wheel moved `quarter` counts, rounded from the exact quarter circle.
+        let turn = 2.0 * quarter as f64 / 1000.0 / 0.5;
+        assert_close(pose.heading.0, turn);
+        assert_close(drive.velocity().angular.0, turn);
+        assert!((turn - PI / 2.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn arc_advances_along_the_mean_heading() {
+        let mut drive = drive();
+        let start = Instant::now();
+        drive.integrate(reading(start, 0, [0, 0]));
+        // The right wheel travels 0.25 m further, turning by 0.5 rad.
+        let pose = drive.integrate(reading(start, 1000, [1000, 1250]));
+        assert_close(pose.heading.0, 0.5);
+        assert_close(pose.x.0, 1.125 * 0.25f64.cos());
+        assert_close(pose.y.0, 1.125 * 0.25f64.sin());
+    }
+
+    #[test]
+    fn stale_readings_are_ignored() {
+        let mut drive = drive();
+        let start = Instant::now();
+        drive.integrate(reading(start, 100, [0, 0]));
+        drive.integrate(reading(start, 200, [100, 100]));
+        let pose = drive.pose();
+        assert_eq!(drive.integrate(reading(start, 200, [900, 900])), pose);
+        assert_eq!(drive.integrate(reading(start, 50, [900, 900])), pose);
+
+        // The next newer reading continues from the last accepted counts.
+        drive.integrate(reading(start, 300, [200, 200]));
+        assert_close(drive.pose().x.0, 0.2);
+    }
+
+    #[test]
+    fn reset_forgets_the_reference() {
+        let mut drive = drive();
+        let start = Instant::now();
+        drive.integrate(reading(start, 0, [0, 0]));
+        drive.integrate(reading(start, 100, [100, 100]));
+        drive.reset();
+        assert_eq!(
+            drive.integrate(reading(start, 200, [900, 900])),
+            Pose::default()
+        );
+        assert_eq!(drive.velocity(), Velocity::default());
+    }
+
+    #[test]
+    fn headings_are_normalized() {
+        assert_close(normalize(0.5), 0.5);
+        assert_close(normalize(3.0 * PI / 2.0), -PI / 2.0);
+        assert_close(normalize(-3.0 * PI / 2.0), PI / 2.0);
+        assert_close(normalize(-PI), PI);
+        assert_close(normalize(5.0 * PI), PI);
+
+        let mut drive = drive();
+        drive.set_pose(Pose {
+            x: Meters(1.0),
+            y: Meters(2.0),
+            heading: Radians(2.5 * PI),
+        });
+        assert_close(drive.pose().heading.0, PI / 2.0);
+        assert_eq!(drive.pose().x, Meters(1.0));
+    }
+}
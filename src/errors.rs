@@ -1,21 +1,170 @@
 //! This module defines error types for the RoboClaw motor controller and
 //! connection-related issues.
-//! It contains two primary error enums:
+//! It contains three primary error types:
 //! - `RoboClawError`: A wrapper for various error types related to RoboClaw
-//! motor controller interactions.
+//!   motor controller interactions.
+//! - `CommandError`: A failed command, carrying the command, the controller
+//!   address and the error of every attempt made.
 //! - `ConnectionError`: Represents errors that occur during the connection setup
-//! or communication with the RoboClaw device.
+//!   or communication with the RoboClaw device.
 //!
-//! The module also includes conversion implementations to allow seamless error
-//! handling from other error types.
+//! All of them implement `std::error::Error`, so they can be propagated with `?`
+//! into `Box<dyn Error>` or similar. The module also includes conversion
+//! implementations to allow seamless error handling from other error types.
+
+use crate::{capabilities::Model, firmware::FirmwareVersion, Commands};
+use std::{error::Error, fmt};
 
 /// `RoboClawError` represents errors encountered while communicating with the
 /// RoboClaw motor controller.
 /// This enum encapsulates different error types related to RoboClaw's connection
 /// or I/O issues.
+#[derive(Debug)]
 pub enum RoboClawError {
     Connection(ConnectionError), // Represents a connection-related error, wrapping a `ConnectionError`.
-    Io(std::io::Error),          // Represents an I/O error, wrapping a `std::io::Error`
+    Command(CommandError), // Represents a command that could not be completed, wrapping a `CommandError`.
+    Io(std::io::Error),    // Represents an I/O error, wrapping a `std::io::Error`
+    UnexpectedValue {
+        command: Commands, // The command whose response contained the value
+        address: u8,       // Address of the RoboClaw that returned the value
+        value: u32,        // The value that was returned
+    }, // Represents a value returned by the RoboClaw that is outside the documented range.
+    InvalidArgument {
+        command: Commands,      // The command the argument was meant for
+        address: u8,            // Address of the RoboClaw the command was meant for
+        argument: &'static str, // Name of the rejected argument
+        value: i64,             // The value that was passed
+        min: i64,               // Smallest accepted value
+        max: i64,               // Largest accepted value
+    }, // Represents an argument outside the range accepted by the RoboClaw.
+    Unsupported {
+        command: Commands,         // The command that was requested
+        address: u8,               // Address of the RoboClaw lacking support
+        required: FirmwareVersion, // Minimum firmware version supporting the command
+        found: FirmwareVersion,    // Firmware version of the connected RoboClaw
+    }, // Represents a command the connected firmware does not support.
+    UnsupportedByModel {
+        command: Commands, // The command that was requested
+        address: u8,       // Address of the RoboClaw lacking support
+        model: Model,      // Model of the connected RoboClaw
+    }, // Represents a command the connected model does not support, e.g. M2 commands on a Solo.
+    UnknownFirmware {
+        address: u8,     // Address of the RoboClaw that reported the firmware string
+        version: String, // The firmware string as reported
+    }, // Represents a firmware string no version could be parsed from.
+    InvalidAddress {
+        address: u8, // The rejected address
+    }, // Represents a packet serial address outside 0x80..=0x87.
+}
+
+impl RoboClawError {
+    /// Returns `true` if the command failed because the serial port disconnected.
+    pub fn is_disconnected(&self) -> bool {
+        match self {
+            RoboClawError::Connection(error) => matches!(error, ConnectionError::Disconnected),
+            RoboClawError::Command(error) => {
+                matches!(error.last_error(), ConnectionError::Disconnected)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the command that failed, if the error is tied to a command.
+    pub fn command(&self) -> Option<Commands> {
+        match self {
+            RoboClawError::Command(error) => Some(error.command),
+            RoboClawError::UnexpectedValue { command, .. }
+            | RoboClawError::InvalidArgument { command, .. }
+            | RoboClawError::Unsupported { command, .. }
+            | RoboClawError::UnsupportedByModel { command, .. } => Some(*command),
+            _ => None,
+        }
+    }
+
+    /// Returns the address of the RoboClaw the error occurred with, if known.
+    pub fn address(&self) -> Option<u8> {
+        match self {
+            RoboClawError::Command(error) => Some(error.address),
+            RoboClawError::UnexpectedValue { address, .. }
+            | RoboClawError::InvalidArgument { address, .. }
+            | RoboClawError::Unsupported { address, .. }
+            | RoboClawError::UnsupportedByModel { address, .. }
+            | RoboClawError::UnknownFirmware { address, .. }
+            | RoboClawError::InvalidAddress { address } => Some(*address),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RoboClawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoboClawError::Connection(error) => write!(f, "connection error: {}", error),
+            RoboClawError::Command(error) => error.fmt(f),
+            RoboClawError::Io(error) => write!(f, "I/O error: {}", error),
+            RoboClawError::UnexpectedValue {
+                command,
+                address,
+                value,
+            } => write!(
+                f,
+                "{:?} on controller {:#04x} returned unexpected value {}",
+                command, address, value
+            ),
+            RoboClawError::InvalidArgument {
+                command,
+                address,
+                argument,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "{:?} on controller {:#04x}: {} = {} is outside {}..={}",
+                command, address, argument, value, min, max
+            ),
+            RoboClawError::Unsupported {
+                command,
+                address,
+                required,
+                found,
+            } => write!(
+                f,
+                "{:?} is unsupported by firmware {} on controller {:#04x}, requires {}",
+                command, found, address, required
+            ),
+            RoboClawError::UnsupportedByModel {
+                command,
+                address,
+                model,
+            } => write!(
+                f,
+                "{:?} is unsupported by the {} on controller {:#04x}",
+                command, model, address
+            ),
+            RoboClawError::UnknownFirmware { address, version } => write!(
+                f,
+                "controller {:#04x} reported unrecognised firmware {:?}",
+                address, version
+            ),
+            RoboClawError::InvalidAddress { address } => write!(
+                f,
+                "address {:#04x} is outside the packet serial range 0x80..=0x87",
+                address
+            ),
+        }
+    }
+}
+
+impl Error for RoboClawError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RoboClawError::Connection(error) => Some(error),
+            RoboClawError::Command(error) => Some(error),
+            RoboClawError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
 }
 
 impl From<ConnectionError> for RoboClawError {
@@ -25,6 +174,13 @@ impl From<ConnectionError> for RoboClawError {
     }
 }
 
+impl From<CommandError> for RoboClawError {
+    /// Converts from `CommandError` to `RoboClawError::Command`
+    fn from(value: CommandError) -> Self {
+        RoboClawError::Command(value)
+    }
+}
+
 impl From<std::io::Error> for RoboClawError {
     /// Converts from `std::io::Error` to `RoboClawError::Io`
     fn from(value: std::io::Error) -> Self {
@@ -32,25 +188,225 @@ impl From<std::io::Error> for RoboClawError {
     }
 }
 
+/// `OutOfRange` describes an argument outside the range the RoboClaw accepts, before
+/// it is tied to a command and controller as `RoboClawError::InvalidArgument`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange {
+    pub argument: &'static str, // Name of the rejected argument
+    pub value: i64,             // The value that was passed
+    pub min: i64,               // Smallest accepted value
+    pub max: i64,               // Largest accepted value
+}
+
+impl OutOfRange {
+    /// Fails unless `value` lies within `min..=max`.
+    pub fn check(argument: &'static str, value: i64, min: i64, max: i64) -> Result<(), Self> {
+        if (min..=max).contains(&value) {
+            Ok(())
+        } else {
+            Err(OutOfRange {
+                argument,
+                value,
+                min,
+                max,
+            })
+        }
+    }
+
+    /// Converts into `RoboClawError::InvalidArgument` for `command` sent to `address`.
+    pub(crate) fn into_error(self, command: Commands, address: u8) -> RoboClawError {
+        RoboClawError::InvalidArgument {
+            command,
+            address,
+            argument: self.argument,
+            value: self.value,
+            min: self.min,
+            max: self.max,
+        }
+    }
+}
+
+/// `CommandError` represents a command that could not be completed by the RoboClaw.
+/// It records which command was sent to which controller and the error of every
+/// attempt that was made, in order.
+#[derive(Debug)]
+pub struct CommandError {
+    pub command: Commands,         // The command that failed
+    pub address: u8,               // Address of the RoboClaw the command was sent to
+    history: Vec<ConnectionError>, // Error of each attempt, never empty, the last one is the final error
+}
+
+impl CommandError {
+    /// Creates the error of `command` sent to `address` that failed with `last`
+    /// after the attempts that failed with the errors in `earlier`.
+    pub fn new(
+        command: Commands,
+        address: u8,
+        mut earlier: Vec<ConnectionError>,
+        last: ConnectionError,
+    ) -> Self {
+        earlier.push(last);
+        CommandError {
+            command,
+            address,
+            history: earlier,
+        }
+    }
+
+    /// Returns the error of each attempt, in order.
+    pub fn history(&self) -> &[ConnectionError] {
+        &self.history
+    }
+
+    /// Returns the number of attempts made before giving up.
+    pub fn attempts(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Returns the error of the last attempt.
+    pub fn last_error(&self) -> &ConnectionError {
+        self.history
+            .last()
+            .expect("`CommandError::new` records at least one attempt")
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} on controller {:#04x} failed after {} attempt(s): {}",
+            self.command,
+            self.address,
+            self.attempts(),
+            self.last_error()
+        )?;
+        if self.history.len() > 1 {
+            write!(f, " (attempts:")?;
+            for (i, error) in self.history.iter().enumerate() {
+                write!(f, " #{}: {};", i + 1, error)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for CommandError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.last_error())
+    }
+}
+
 /// `ConnectionError` represents errors that occure when trying to maintain or communicate
 /// with the RoboClaw motor controller. It covers different kinds of connection failures.
+#[derive(Debug)]
 pub enum ConnectionError {
     Io(std::io::Error), // Represents a generic I/O error that occurs during connection handling.
     Serial(serialport::Error), // Represents an error specific to the serial port connection.
-    InvalidByteSize(u8), // Represents an error where the byte size value is invalid
-    CRCMismatch,        // Represents a CRC (Cyclic Redundancy Check) mismatch error.
+    NoResponse,         // Represents a timeout without a single byte received from the RoboClaw.
+    ShortResponse {
+        expected: usize, // Number of bytes the response should have had, at least
+        received: usize, // Number of bytes received before the timeout
+    }, // Represents a response that stopped before it was complete.
+    BadAck(u8),         // Represents an acknowledgement byte other than 0xFF.
+    CRCMismatch {
+        expected: u16, // CRC computed over the packet
+        actual: u16,   // CRC received from the RoboClaw
+    }, // Represents a CRC (Cyclic Redundancy Check) mismatch error.
+    Unverified, // Represents a lost acknowledgement of a non-idempotent command that may or may not have been queued.
+    Disconnected, // Represents a serial port that disappeared, e.g. an unplugged USB cable.
+}
+
+impl ConnectionError {
+    /// Returns `true` for errors in the exchange with the RoboClaw itself, as opposed
+    /// to errors of the underlying serial port.
+    pub fn is_protocol_error(&self) -> bool {
+        matches!(
+            self,
+            ConnectionError::NoResponse
+                | ConnectionError::ShortResponse { .. }
+                | ConnectionError::BadAck(_)
+                | ConnectionError::CRCMismatch { .. }
+        )
+    }
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::Io(error) => write!(f, "I/O error: {}", error),
+            ConnectionError::Serial(error) => write!(f, "serial port error: {}", error),
+            ConnectionError::NoResponse => write!(f, "no response"),
+            ConnectionError::ShortResponse { expected, received } => write!(
+                f,
+                "short response, received {} of {} bytes",
+                received, expected
+            ),
+            ConnectionError::BadAck(ack) => write!(f, "bad acknowledgement {:#04x}", ack),
+            ConnectionError::Disconnected => write!(f, "serial port disconnected"),
+            ConnectionError::Unverified => {
+                write!(f, "acknowledgement lost and delivery could not be verified")
+            }
+            ConnectionError::CRCMismatch { expected, actual } => write!(
+                f,
+                "CRC mismatch, expected {:#06x} but received {:#06x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl Error for ConnectionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConnectionError::Io(error) => Some(error),
+            ConnectionError::Serial(error) => Some(error),
+            _ => None,
+        }
+    }
 }
 
 impl From<std::io::Error> for ConnectionError {
-    /// Converts `std::io::Error` to `ConnectionError::Io`
+    /// Converts `std::io::Error` to `ConnectionError::Io`, or to
+    /// `ConnectionError::Disconnected` for `ErrorKind::NotConnected`
     fn from(value: std::io::Error) -> Self {
-        ConnectionError::Io(value)
+        match value.kind() {
+            std::io::ErrorKind::NotConnected => ConnectionError::Disconnected,
+            _ => ConnectionError::Io(value),
+        }
     }
 }
 
 impl From<serialport::Error> for ConnectionError {
-    /// Converts `serialport::Error` to `ConnectionError::Serial`
+    /// Converts `serialport::Error` to `ConnectionError::Serial`, or to
+    /// `ConnectionError::Disconnected` for a missing device
     fn from(value: serialport::Error) -> Self {
-        ConnectionError::Serial(value)
+        match value.kind() {
+            serialport::ErrorKind::NoDevice
+            | serialport::ErrorKind::Io(std::io::ErrorKind::NotConnected) => {
+                ConnectionError::Disconnected
+            }
+            _ => ConnectionError::Serial(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_error_reports_every_attempt() {
+        let error = CommandError::new(
+            Commands::ReadStatus,
+            0x80,
+            vec![ConnectionError::NoResponse],
+            ConnectionError::BadAck(0x00),
+        );
+        assert_eq!(error.attempts(), 2);
+        assert!(matches!(error.last_error(), ConnectionError::BadAck(0x00)));
+        assert!(error.source().is_some());
+        assert!(error.to_string().contains("failed after 2 attempt(s)"));
     }
 }
@@ -0,0 +1,97 @@
+//! This module handles the RoboClaw firmware version.
+//!
+//! The RoboClaw reports its firmware as a string such as
+//! `"USB Roboclaw 2x15a v4.1.34\n"`. Newer firmware adds commands and extends
+//! some responses, so the parsed `FirmwareVersion` is used to reject commands the
+//! connected controller does not know about instead of waiting for a timeout.
+
+use std::fmt;
+
+/// A firmware version as reported by the RoboClaw, ordered by major, minor and
+/// patch number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u8,  // Major version number
+    pub minor: u8,  // Minor version number
+    pub patch: u16, // Patch version number
+}
+
+impl FirmwareVersion {
+    /// Creates a new `FirmwareVersion`.
+    pub const fn new(major: u8, minor: u8, patch: u16) -> Self {
+        FirmwareVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses the version out of a firmware string as returned by
+    /// `Roboclaw::read_version`. Returns `None` if no `v<major>.<minor>.<patch>`
+    /// part could be found.
+    pub fn parse(version: &str) -> Option<Self> {
+        version.split_whitespace().rev().find_map(|word| {
+            let mut numbers = word.strip_prefix(['v', 'V'])?.split('.');
+            let version = FirmwareVersion {
+                major: numbers.next()?.parse().ok()?,
+                minor: numbers.next()?.parse().ok()?,
+                patch: numbers.next()?.parse().ok()?,
+            };
+            match numbers.next() {
+                None => Some(version),
+                Some(_) => None,
+            }
+        })
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_version_of_a_firmware_string() {
+        assert_eq!(
+            FirmwareVersion::parse("USB Roboclaw 2x15a v4.1.34\n"),
+            Some(FirmwareVersion::new(4, 1, 34))
+        );
+        assert_eq!(
+            FirmwareVersion::parse("RoboClaw Solo 60A V4.2.8"),
+            Some(FirmwareVersion::new(4, 2, 8))
+        );
+        assert_eq!(
+            FirmwareVersion::parse("v1.0.300").unwrap().to_string(),
+            "v1.0.300"
+        );
+    }
+
+    #[test]
+    fn malformed_versions_are_not_parsed() {
+        for version in [
+            "",
+            "USB Roboclaw 2x15a",
+            "USB Roboclaw 2x15a 4.1.34",
+            "USB Roboclaw 2x15a v4.1",
+            "USB Roboclaw 2x15a v4.1.34.2",
+            "USB Roboclaw 2x15a v4.1.x",
+            "USB Roboclaw 2x15a v4..34",
+            "USB Roboclaw 2x15a v256.1.34",
+            "USB Roboclaw 2x15a v-4.1.34",
+        ] {
+            assert_eq!(FirmwareVersion::parse(version), None, "{:?}", version);
+        }
+    }
+
+    #[test]
+    fn versions_are_ordered_by_number() {
+        assert!(FirmwareVersion::new(4, 1, 34) < FirmwareVersion::new(4, 2, 0));
+        assert!(FirmwareVersion::new(4, 10, 0) > FirmwareVersion::new(4, 9, 99));
+        assert!(FirmwareVersion::new(5, 0, 0) > FirmwareVersion::new(4, 255, 65535));
+    }
+}
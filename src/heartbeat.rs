@@ -0,0 +1,65 @@
+//! This module provides a background heartbeat for the RoboClaw serial timeout.
+//!
+//! When a serial timeout is configured with `Roboclaw::set_serial_timeout`, the
+//! RoboClaw stops both motors if no valid packet arrives within that window. The
+//! `Heartbeat` keeps sending a cheap read command while the application reports
+//! itself healthy, so the motors only stop once the application stops feeding it
+//! or the process dies.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Handle to a running heartbeat thread. The thread is stopped when the handle
+/// is dropped.
+pub struct Heartbeat {
+    stop: Arc<AtomicBool>,          // Set to request the heartbeat thread to exit
+    thread: Option<JoinHandle<()>>, // The heartbeat thread, taken when joining
+}
+
+impl Heartbeat {
+    /// Spawns the heartbeat thread. Every `interval` the `healthy` check is called
+    /// and, if it returns `true`, `beat` sends a cheap read to the RoboClaw. The
+    /// thread exits early once `beat` returns `false`.
+    pub(crate) fn spawn<F, B>(interval: Duration, mut healthy: F, mut beat: B) -> Self
+    where
+        F: FnMut() -> bool + Send + 'static,
+        B: FnMut() -> bool + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            while !thread_stop.load(Ordering::Acquire) {
+                if healthy() && !beat() {
+                    return;
+                }
+                thread::park_timeout(interval);
+            }
+        });
+
+        Heartbeat {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Stops the heartbeat and waits for the thread to exit.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::Release);
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
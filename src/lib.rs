@@ -1,12 +1,48 @@
+pub mod builder;
+pub mod capabilities;
+pub mod codec;
 mod commands;
 pub mod connection;
+pub mod diff_drive;
 pub mod errors;
+pub mod firmware;
+pub mod heartbeat;
+pub mod motor;
+pub mod port;
+pub mod reconnect;
+pub mod replay;
+pub mod retry;
+pub mod schema;
+pub mod shared;
+pub mod stats;
+mod sync;
+pub mod telemetry;
+pub mod timestamp;
+pub mod trace;
+pub mod transport;
+pub mod units;
+
+pub use commands::Commands;
 
 use bitflags::bitflags;
-use commands::Commands;
+use builder::{RoboclawBuilder, Timeouts};
+use capabilities::{Capabilities, Model};
+use commands::EXTENDED_FIRMWARE;
 use connection::Connection;
-use errors::RoboClawError;
+use errors::{ConnectionError, OutOfRange, RoboClawError};
+use firmware::FirmwareVersion;
+use heartbeat::Heartbeat;
+use motor::{carrier, Motor, MotorCommand, MotorReading};
+use port::{PortSpec, ROBOCLAW_USB_PID, ROBOCLAW_USB_VID};
+use retry::RetryPolicy;
 use serialport::SerialPort;
+use stats::Stats;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::time::Instant;
+use timestamp::Timestamped;
+use trace::TraceSink;
+use transport::Transport;
 
 bitflags! {
     pub struct ConfigFlags: u16 {
@@ -51,36 +87,105 @@ bitflags! {
 }
 
 bitflags! {
-    pub struct StatusFlags: u16 {
-        const NORMAL = 0x0000;
-        const M1_OVERCURRENT_WARNING = 0x0001;
-        const M2_OVERCURRENT_WARNING = 0x0002;
-        const E_STOP = 0x0004;
-        const TEMPERATURE_ERROR = 0x0008;
-        const TEMPERATURE2_ERROR = 0x0010;
-        const MAIN_BATTERY_HIGH_ERROR = 0x0020;
-        const LOGIC_BATTERY_HIGH_ERROR = 0x0040;
-        const LOGIC_BATTERY_LOW_ERROR = 0x0080;
-        const M1_DRIVER_FAULT = 0x0100;
-        const M2_DRIVER_FAULT = 0x0200;
-        const MAIN_BATTERY_HIGH_WARNING = 0x0400;
-        const MAIN_BATTERY_LOW_WARNING = 0x0800;
-        const TERMPERATURE_WARNING = 0x1000;
-        const TEMPERATURE2_WARNING = 0x2000;
-        const M1_HOME = 0x4000;
-        const M2_HOME = 0x8000;
+    /// Status reported by `Roboclaw::read_error`. The bits follow the extended 32-bit
+    /// layout of firmware 4.2 and later; the 16-bit layout of older firmware is
+    /// mapped onto them by `StatusFlags::from_legacy`.
+    pub struct StatusFlags: u32 {
+        const NORMAL = 0x0000_0000;
+        const E_STOP = 0x0000_0001;
+        const TEMPERATURE_ERROR = 0x0000_0002;
+        const TEMPERATURE2_ERROR = 0x0000_0004;
+        const MAIN_BATTERY_HIGH_ERROR = 0x0000_0008;
+        const LOGIC_BATTERY_HIGH_ERROR = 0x0000_0010;
+        const LOGIC_BATTERY_LOW_ERROR = 0x0000_0020;
+        const M1_DRIVER_FAULT = 0x0000_0040;
+        const M2_DRIVER_FAULT = 0x0000_0080;
+        const M1_SPEED_ERROR = 0x0000_0100;
+        const M2_SPEED_ERROR = 0x0000_0200;
+        const M1_POSITION_ERROR = 0x0000_0400;
+        const M2_POSITION_ERROR = 0x0000_0800;
+        const M1_CURRENT_ERROR = 0x0000_1000;
+        const M2_CURRENT_ERROR = 0x0000_2000;
+        const M1_HOME = 0x0000_4000; // Only reported by the 16-bit layout
+        const M2_HOME = 0x0000_8000; // Only reported by the 16-bit layout
+        const M1_OVERCURRENT_WARNING = 0x0001_0000;
+        const M2_OVERCURRENT_WARNING = 0x0002_0000;
+        const MAIN_BATTERY_HIGH_WARNING = 0x0004_0000;
+        const MAIN_BATTERY_LOW_WARNING = 0x0008_0000;
+        const TERMPERATURE_WARNING = 0x0010_0000;
+        const TEMPERATURE2_WARNING = 0x0020_0000;
+        const S4_SIGNAL_TRIGGERED = 0x0040_0000;
+        const S5_SIGNAL_TRIGGERED = 0x0080_0000;
+        const SPEED_ERROR_LIMIT_WARNING = 0x0100_0000;
+        const POSITION_ERROR_LIMIT_WARNING = 0x0200_0000;
     }
 }
 
-#[derive(PartialEq, Debug)]
+/// The bits of the 16-bit status layout and the flags they map to.
+const LEGACY_STATUS: [(u16, StatusFlags); 16] = [
+    (0x0001, StatusFlags::M1_OVERCURRENT_WARNING),
+    (0x0002, StatusFlags::M2_OVERCURRENT_WARNING),
+    (0x0004, StatusFlags::E_STOP),
+    (0x0008, StatusFlags::TEMPERATURE_ERROR),
+    (0x0010, StatusFlags::TEMPERATURE2_ERROR),
+    (0x0020, StatusFlags::MAIN_BATTERY_HIGH_ERROR),
+    (0x0040, StatusFlags::LOGIC_BATTERY_HIGH_ERROR),
+    (0x0080, StatusFlags::LOGIC_BATTERY_LOW_ERROR),
+    (0x0100, StatusFlags::M1_DRIVER_FAULT),
+    (0x0200, StatusFlags::M2_DRIVER_FAULT),
+    (0x0400, StatusFlags::MAIN_BATTERY_HIGH_WARNING),
+    (0x0800, StatusFlags::MAIN_BATTERY_LOW_WARNING),
+    (0x1000, StatusFlags::TERMPERATURE_WARNING),
+    (0x2000, StatusFlags::TEMPERATURE2_WARNING),
+    (0x4000, StatusFlags::M1_HOME),
+    (0x8000, StatusFlags::M2_HOME),
+];
+
+impl StatusFlags {
+    /// Decodes the 16-bit status of firmware older than 4.2.
+    pub fn from_legacy(status: u16) -> Self {
+        LEGACY_STATUS
+            .iter()
+            .filter(|(bit, _)| status & bit != 0)
+            .fold(StatusFlags::NORMAL, |flags, (_, flag)| flags | *flag)
+    }
+
+    /// Decodes the extended 32-bit status of firmware 4.2 and later.
+    pub fn from_extended(status: u32) -> Self {
+        StatusFlags::from_bits_truncate(status) - (StatusFlags::M1_HOME | StatusFlags::M2_HOME)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum BufferStatus {
     NotEmpty(u8),
     Empty,
     LastCommandExecuting,
 }
 
+/// The highest location of the user EEPROM, which holds 256 bytes as 128 16-bit
+/// values.
+pub const MAX_USER_MEMORY_LOCATION: u8 = 127;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PwmMode {
+    LockedAntiphase = 0,
+    SignMagnitude = 1,
+}
+
+/// The mode of a CTRL output.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CtrlMode {
+    Disabled = 0,     // The output is off
+    User = 1,         // The output follows `Roboclaw::set_ctrl1` and `set_ctrl2`
+    VoltageClamp = 2, // The output switches a voltage clamp
+    Brake = 3,        // The output switches a brake
+}
+
 pub struct Roboclaw {
     connection: Connection,
+    capabilities: Option<Capabilities>, // Cached capabilities, probed on first use
+    unknown_version: Option<String>,    // Cached firmware string that could not be parsed
 }
 
 impl Roboclaw {
@@ -89,173 +194,660 @@ impl Roboclaw {
         address: u8,
         tries: Option<u8>,
     ) -> Result<Self, RoboClawError> {
-        let tries: u8 = tries.unwrap_or_else(|| 3);
-        let connection: Connection = Connection::new(port, address, tries)?;
-        Ok(Roboclaw { connection })
+        let mut retry_policy = RetryPolicy::default();
+        if let Some(tries) = tries {
+            retry_policy = retry_policy.max_attempts(tries);
+        }
+        let connection: Connection = Connection::new(Box::new(port), address, retry_policy)?;
+        Ok(Roboclaw::from_connection(connection))
+    }
+
+    /// Returns a builder opening the port and setting up the `Roboclaw`, see the
+    /// `builder` module.
+    pub fn builder() -> RoboclawBuilder {
+        RoboclawBuilder::new()
+    }
+
+    pub(crate) fn from_connection(connection: Connection) -> Self {
+        Roboclaw {
+            connection,
+            capabilities: None,
+            unknown_version: None,
+        }
+    }
+
+    /// Creates a `Roboclaw` talking to the controller at `address` over `transport`
+    /// instead of a serial port, e.g. a `replay::ReplayTransport` in tests. The
+    /// default retry policy is used.
+    pub fn from_transport<T: Transport + 'static>(
+        transport: T,
+        address: u8,
+    ) -> Result<Self, RoboClawError> {
+        let connection = Connection::new(Box::new(transport), address, RetryPolicy::default())?;
+        Ok(Roboclaw::from_connection(connection))
+    }
+
+    /// Opens the port described by `spec` and creates a `Roboclaw` talking to the
+    /// controller at `address` with the default retry policy.
+    pub fn open(spec: &PortSpec, address: u8) -> Result<Self, RoboClawError> {
+        let port = spec
+            .open(Duration::from_millis(5))
+            .map_err(|error| RoboClawError::Connection(ConnectionError::Serial(error)))?;
+        Roboclaw::new(port, address, None)
+    }
+
+    /// Opens the RoboClaw with the USB `serial_number`, wherever it is enumerated.
+    pub fn open_usb(
+        serial_number: &str,
+        baud_rate: u32,
+        address: u8,
+    ) -> Result<Self, RoboClawError> {
+        let spec = PortSpec::usb(
+            ROBOCLAW_USB_VID,
+            ROBOCLAW_USB_PID,
+            Some(serial_number.to_owned()),
+            baud_rate,
+        );
+        Roboclaw::open(&spec, address)
+    }
+
+    /// Opens the first attached RoboClaw whose user EEPROM `location` holds `tag`,
+    /// as written with `write_user_memory`. Controllers that cannot be opened or do
+    /// not answer are skipped. Fails before opening any port if `location` is above
+    /// `MAX_USER_MEMORY_LOCATION`.
+    pub fn open_by_tag(
+        location: u8,
+        tag: u16,
+        baud_rate: u32,
+        address: u8,
+    ) -> Result<Self, RoboClawError> {
+        check_user_memory_location(location)
+            .map_err(|error| error.into_error(Commands::EEPROMReadUserMemoryLocation, address))?;
+        let ports = port::list_roboclaws()
+            .map_err(|error| RoboClawError::Connection(ConnectionError::Serial(error)))?;
+        for info in ports {
+            let spec = PortSpec::path(info.path, baud_rate);
+            let mut roboclaw = match Roboclaw::open(&spec, address) {
+                Ok(roboclaw) => roboclaw,
+                Err(_) => continue,
+            };
+            if let Ok(value) = roboclaw.read_user_memory(location) {
+                if value == tag {
+                    return Ok(roboclaw);
+                }
+            }
+        }
+        Err(RoboClawError::Connection(ConnectionError::Serial(
+            serialport::Error::new(
+                serialport::ErrorKind::NoDevice,
+                format!(
+                    "no attached RoboClaw holds tag {:#06x} at user EEPROM location {}",
+                    tag, location
+                ),
+            ),
+        )))
+    }
+
+    /// Returns the response timeouts per command class.
+    pub fn timeouts(&self) -> Timeouts {
+        self.connection.timeouts
+    }
+
+    /// Sets the response timeouts per command class. A timeout set on the retry
+    /// policy takes precedence.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.connection.timeouts = timeouts;
+    }
+
+    /// Returns the retry policy used for commands sent to this RoboClaw.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.connection.retry_policy
     }
 
+    /// Sets the retry policy used for commands sent to this RoboClaw.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.connection.retry_policy = retry_policy;
+    }
+
+    /// Returns the counters and latencies of the commands sent to this RoboClaw since
+    /// it was opened or the statistics were last reset.
+    pub fn stats(&self) -> &Stats {
+        &self.connection.stats
+    }
+
+    /// Resets all statistics to zero.
+    pub fn reset_stats(&mut self) {
+        self.connection.stats = Stats::default();
+    }
+
+    /// Starts tracing every frame sent to and received from this RoboClaw into
+    /// `sink`, replacing any previous sink. Use `trace::TraceWriter` to write the
+    /// trace to a file.
+    pub fn set_trace<S: TraceSink + 'static>(&mut self, sink: S) {
+        self.connection.set_trace(Some(Box::new(sink)));
+    }
+
+    /// Stops tracing and drops the trace sink.
+    pub fn clear_trace(&mut self) {
+        self.connection.set_trace(None);
+    }
+
+    /// Runs `f` with `retry_policy` in place of the configured policy, e.g. to give a
+    /// single EEPROM write more time. The configured policy is restored afterwards.
+    pub fn with_retry_policy<T>(
+        &mut self,
+        retry_policy: RetryPolicy,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let previous = std::mem::replace(&mut self.connection.retry_policy, retry_policy);
+        let result = f(self);
+        self.connection.retry_policy = previous;
+        result
+    }
+
+    /// Runs `f` and returns its result with the host times of the exchanges it
+    /// made, e.g. `roboclaw.timestamped(Roboclaw::read_encoders)`. If `f` sends
+    /// several commands, `sent` is the time the first request was sent and
+    /// `received` the time the last response arrived. If `f` sent nothing, e.g.
+    /// because it returned a cached value, both are the time `f` returned.
+    pub fn timestamped<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, RoboClawError>,
+    ) -> Result<Timestamped<T>, RoboClawError> {
+        let outer = self.connection.exchanges.take();
+        let result = f(self);
+        let span = self.connection.exchanges;
+        // Nested calls extend the span of the enclosing call.
+        self.connection.exchanges = match (outer, span) {
+            (Some((sent, _)), Some((_, received))) => Some((sent, received)),
+            (outer, span) => span.or(outer),
+        };
+        let value = result?;
+        let (sent, received) = span.unwrap_or_else(|| {
+            let now = Instant::now();
+            (now, now)
+        });
+        Ok(Timestamped {
+            value,
+            sent,
+            received,
+        })
+    }
+
+    /// Reads the firmware string of the RoboClaw, e.g. `"USB Roboclaw 2x15a v4.1.34"`.
+    pub fn read_version(&mut self) -> Result<String, RoboClawError> {
+        let version = self
+            .connection
+            .read::<String>(Commands::ReadFirmwareVersion)?;
+        Ok(version.trim_end().to_string())
+    }
+
+    /// Returns the firmware version of the RoboClaw, see `capabilities`.
+    pub fn firmware_version(&mut self) -> Result<FirmwareVersion, RoboClawError> {
+        Ok(self.capabilities()?.firmware)
+    }
+
+    /// Returns the model and firmware capabilities of the RoboClaw. They are probed
+    /// once, when the `Roboclaw` is built or on first use, and cached for the
+    /// lifetime of this `Roboclaw`.
+    pub fn capabilities(&mut self) -> Result<Capabilities, RoboClawError> {
+        match (self.capabilities, &self.unknown_version) {
+            (Some(capabilities), _) => Ok(capabilities),
+            (None, Some(version)) => Err(RoboClawError::UnknownFirmware {
+                address: self.connection.address,
+                version: version.clone(),
+            }),
+            (None, None) => self.probe(),
+        }
+    }
+
+    /// Reads the firmware string and derives the capabilities from it, replacing
+    /// the cached ones, e.g. after a firmware update.
+    pub fn probe(&mut self) -> Result<Capabilities, RoboClawError> {
+        let version = self.read_version()?;
+        let firmware = match FirmwareVersion::parse(&version) {
+            Some(firmware) => firmware,
+            None => {
+                self.capabilities = None;
+                self.unknown_version = Some(version.clone());
+                return Err(RoboClawError::UnknownFirmware {
+                    address: self.connection.address,
+                    version,
+                });
+            }
+        };
+        let capabilities = Capabilities::new(Model::parse(&version), firmware);
+        self.capabilities = Some(capabilities);
+        self.unknown_version = None;
+        Ok(capabilities)
+    }
+
+    /// Checks that the connected RoboClaw supports `command`, so that unsupported
+    /// commands fail early instead of timing out. The capabilities are only probed
+    /// for commands that need a newer firmware; the model is checked once they are
+    /// known, so a stop command never waits for a probe.
+    fn require(&mut self, command: Commands) -> Result<(), RoboClawError> {
+        let capabilities = match self.capabilities {
+            Some(capabilities) => capabilities,
+            None if command.min_firmware().is_some() => self.capabilities()?,
+            None => return Ok(()),
+        };
+        if let Some(required) = command.min_firmware() {
+            if capabilities.firmware < required {
+                return Err(RoboClawError::Unsupported {
+                    command,
+                    address: self.connection.address,
+                    required,
+                    found: capabilities.firmware,
+                });
+            }
+        }
+        if !capabilities.model.supports(command) {
+            return Err(RoboClawError::UnsupportedByModel {
+                command,
+                address: self.connection.address,
+                model: capabilities.model,
+            });
+        }
+        Ok(())
+    }
+
+    /// Starts a background `Heartbeat` that keeps the serial timeout from expiring.
+    /// Every `interval` the `healthy` check is called and, while it returns `true`,
+    /// a cheap read is sent to the RoboClaw. The interval should be well below the
+    /// timeout configured with `set_serial_timeout`. See also
+    /// `SharedRoboclaw::spawn_heartbeat`.
+    pub fn spawn_heartbeat<F>(
+        roboclaw: &Arc<Mutex<Roboclaw>>,
+        interval: Duration,
+        healthy: F,
+    ) -> Heartbeat
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        let roboclaw = Arc::clone(roboclaw);
+        Heartbeat::spawn(interval, healthy, move || match roboclaw.lock() {
+            Ok(mut roboclaw) => {
+                roboclaw.beat();
+                true
+            }
+            Err(_) => false,
+        })
+    }
+
+    /// Sends the cheap read of a heartbeat. A failed heartbeat is not fatal, the
+    /// next one may get through before the serial timeout expires.
+    pub(crate) fn beat(&mut self) {
+        let _ = self.read_main_battery_voltage();
+    }
+
+    /// Sends `command` to `motor`, see the `motor` module. `Motor::Both` sends the
+    /// same arguments to both motors, like `send_each`. Fails with
+    /// `RoboClawError::InvalidArgument` without sending anything if an argument is
+    /// outside its documented range.
+    pub fn send<C: MotorCommand>(
+        &mut self,
+        motor: Motor,
+        command: C,
+    ) -> Result<bool, RoboClawError> {
+        self.send_to(motor, &command, &command)
+    }
+
+    /// Sends `m1` to M1 and `m2` to M2, in a single packet if the RoboClaw has a
+    /// command for both motors. Both are validated before either is sent.
+    pub fn send_each<C: MotorCommand>(&mut self, m1: C, m2: C) -> Result<bool, RoboClawError> {
+        self.send_to(Motor::Both, &m1, &m2)
+    }
+
+    fn send_to<C: MotorCommand>(
+        &mut self,
+        motor: Motor,
+        m1: &C,
+        m2: &C,
+    ) -> Result<bool, RoboClawError> {
+        let address = self.connection.address;
+        let capabilities = self.capabilities;
+        let validate = |args: &C, index: usize| {
+            let command = carrier::<C>(motor, index);
+            args.validate()
+                .and_then(|_| match &capabilities {
+                    Some(capabilities) => args.validate_for(capabilities),
+                    None => Ok(()),
+                })
+                .map_err(|error| error.into_error(command, address))
+        };
+        if motor != Motor::M2 {
+            validate(m1, 0)?;
+        }
+        if motor != Motor::M1 {
+            validate(m2, 1)?;
+        }
+        match (motor, C::BOTH) {
+            (Motor::M1, _) => self.write_supported(C::COMMANDS[0], m1),
+            (Motor::M2, _) => self.write_supported(C::COMMANDS[1], m2),
+            (Motor::Both, Some(both)) => self.write_supported(both, &motor::Both(m1, m2)),
+            (Motor::Both, None) => {
+                // Check both first, so a Solo does not drive M1 before M2 fails.
+                self.require(C::COMMANDS[1])?;
+                Ok(self.write_supported(C::COMMANDS[0], m1)?
+                    && self.write_supported(C::COMMANDS[1], m2)?)
+            }
+        }
+    }
+
+    /// Checks the power of a 7-bit command, 0 to 127.
+    fn check_power(&self, command: Commands, speed: u8) -> Result<(), RoboClawError> {
+        OutOfRange::check("speed", speed as i64, 0, 127)
+            .map_err(|error| error.into_error(command, self.connection.address))
+    }
+
+    /// Checks `argument` of `command` against the documented range `min..=max`.
+    fn check(
+        &self,
+        command: Commands,
+        argument: &'static str,
+        value: i64,
+        min: i64,
+        max: i64,
+    ) -> Result<(), RoboClawError> {
+        OutOfRange::check(argument, value, min, max)
+            .map_err(|error| error.into_error(command, self.connection.address))
+    }
+
+    /// Writes `command` after checking that the firmware supports it.
+    fn write_supported<T: codec::Encode + ?Sized>(
+        &mut self,
+        command: Commands,
+        args: &T,
+    ) -> Result<bool, RoboClawError> {
+        self.require(command)?;
+        Ok(self.connection.write(command, args)?)
+    }
+
+    /// Reads `R` from `motor`, see the `motor` module. Fails with
+    /// `RoboClawError::InvalidArgument` for `Motor::Both`, use `read_each` instead.
+    pub fn read_motor<R: MotorReading>(&mut self, motor: Motor) -> Result<R, RoboClawError> {
+        let index = match motor {
+            Motor::M1 => 0,
+            Motor::M2 => 1,
+            Motor::Both => {
+                return Err(RoboClawError::InvalidArgument {
+                    command: R::COMMANDS[0],
+                    address: self.connection.address,
+                    argument: "motor",
+                    value: 3,
+                    min: 1,
+                    max: 2,
+                })
+            }
+        };
+        if R::SHARED {
+            let [m1, m2] = self.read_each::<R>()?;
+            return Ok(if index == 0 { m1 } else { m2 });
+        }
+        self.require(R::COMMANDS[index])?;
+        Ok(self.connection.read(R::COMMANDS[index])?)
+    }
+
+    /// Reads `R` from M1 and then from M2, in one exchange if a single command
+    /// answers for both motors.
+    pub fn read_each<R: MotorReading>(&mut self) -> Result<[R; 2], RoboClawError> {
+        if R::SHARED {
+            self.require(R::COMMANDS[0])?;
+            return Ok(self.connection.read(R::COMMANDS[0])?);
+        }
+        Ok([self.read_motor(Motor::M1)?, self.read_motor(Motor::M2)?])
+    }
+
+    #[deprecated(note = "use `Roboclaw::send(Motor::M1, Forward { .. })`")]
     pub fn forward_m1(&mut self, speed: u8) -> Result<bool, RoboClawError> {
+        self.send(Motor::M1, motor::Forward { speed })
+    }
+
+    #[deprecated(note = "use `Roboclaw::send(Motor::M1, Backward { .. })`")]
+    pub fn backward_m1(&mut self, speed: u8) -> Result<bool, RoboClawError> {
+        self.send(Motor::M1, motor::Backward { speed })
+    }
+
+    /// Sets the main battery voltage below which the motors are stopped, as
+    /// `(volts - 6) * 5`: 0 is 6 V and 140 is 34 V.
+    pub fn set_min_main_voltage(&mut self, level: u8) -> Result<bool, RoboClawError> {
+        self.check(Commands::SetMinMainVoltage, "level", level.into(), 0, 140)?;
+        Ok(self.connection.write(Commands::SetMinMainVoltage, &level)?)
+    }
+
+    /// Sets the main battery voltage above which the RoboClaw brakes hard to protect
+    /// a power supply from regenerated energy, as `volts * 5.12`: 30 is 6 V and 175
+    /// is 34 V.
+    pub fn set_max_main_voltage(&mut self, level: u8) -> Result<bool, RoboClawError> {
+        self.check(Commands::SetMaxMainVoltage, "level", level.into(), 30, 175)?;
+        Ok(self.connection.write(Commands::SetMaxMainVoltage, &level)?)
+    }
+
+    /// Sets the logic battery voltage below which the motors are stopped, as
+    /// `(volts - 6) * 5` like `set_min_main_voltage`. Superseded by
+    /// `set_logic_battery_voltages`.
+    pub fn set_min_logic_voltage(&mut self, level: u8) -> Result<bool, RoboClawError> {
+        self.check(Commands::SetMinLogicVoltage, "level", level.into(), 0, 140)?;
         Ok(self
             .connection
-            .write(Commands::M1Forward, &[speed as u32], &[1])?)
+            .write(Commands::SetMinLogicVoltage, &level)?)
     }
 
-    pub fn backward_m1(&mut self, speed: u8) -> Result<bool, RoboClawError> {
+    /// Sets the logic battery voltage above which the motors are stopped, as
+    /// `volts * 5.12` like `set_max_main_voltage`. Superseded by
+    /// `set_logic_battery_voltages`.
+    pub fn set_max_logic_voltage(&mut self, level: u8) -> Result<bool, RoboClawError> {
+        self.check(Commands::SetMaxLogicVoltage, "level", level.into(), 30, 175)?;
         Ok(self
             .connection
-            .write(Commands::M1Backward, &[speed as u32], &[1])?)
+            .write(Commands::SetMaxLogicVoltage, &level)?)
     }
 
-    pub fn set_min_voltage_main_battery(_voltage: u8) {
-        unimplemented!()
+    #[deprecated(
+        note = "use `Roboclaw::set_min_main_voltage`; this was an associated function that panicked and is now a method sending the level"
+    )]
+    pub fn set_min_voltage_main_battery(&mut self, voltage: u8) -> Result<bool, RoboClawError> {
+        self.set_min_main_voltage(voltage)
     }
 
-    pub fn set_max_voltage_main_battery(_voltage: u8) {
-        unimplemented!()
+    #[deprecated(
+        note = "use `Roboclaw::set_max_main_voltage`; this was an associated function that panicked and is now a method sending the level"
+    )]
+    pub fn set_max_voltage_main_battery(&mut self, voltage: u8) -> Result<bool, RoboClawError> {
+        self.set_max_main_voltage(voltage)
     }
 
+    #[deprecated(note = "use `Roboclaw::send(Motor::M2, Forward { .. })`")]
     pub fn forward_m2(&mut self, speed: u8) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::M2Forward, &[speed as u32], &[1])?)
+        self.send(Motor::M2, motor::Forward { speed })
     }
 
+    #[deprecated(note = "use `Roboclaw::send(Motor::M2, Backward { .. })`")]
     pub fn backward_m2(&mut self, speed: u8) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::M2Backward, &[speed as u32], &[1])?)
+        self.send(Motor::M2, motor::Backward { speed })
     }
 
+    #[deprecated(note = "use `Roboclaw::send(Motor::M1, ForwardBackward { .. })`")]
     pub fn forward_backward_m1(&mut self, speed: u8) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::M1Drive, &[speed as u32], &[1])?)
+        self.send(Motor::M1, motor::ForwardBackward { speed })
     }
 
+    #[deprecated(note = "use `Roboclaw::send(Motor::M2, ForwardBackward { .. })`")]
     pub fn forward_backward_m2(&mut self, speed: u8) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::M2Drive, &[speed as u32], &[1])?)
+        self.send(Motor::M2, motor::ForwardBackward { speed })
     }
 
     pub fn forward_mixed(&mut self, speed: u8) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::MixDriveForward, &[speed as u32], &[1])?)
+        self.check_power(Commands::MixDriveForward, speed)?;
+        Ok(self.connection.write(Commands::MixDriveForward, &speed)?)
     }
 
     pub fn backward_mixed(&mut self, speed: u8) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::MixDriveBackward, &[speed as u32], &[1])?)
+        self.check_power(Commands::MixDriveBackward, speed)?;
+        Ok(self.connection.write(Commands::MixDriveBackward, &speed)?)
     }
 
     pub fn turn_right_mixed(&mut self, speed: u8) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::MixTurnRight, &[speed as u32], &[1])?)
+        self.check_power(Commands::MixTurnRight, speed)?;
+        Ok(self.connection.write(Commands::MixTurnRight, &speed)?)
     }
 
     pub fn turn_left_mixed(&mut self, speed: u8) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::MixTurnLeft, &[speed as u32], &[1])?)
+        self.check_power(Commands::MixTurnLeft, speed)?;
+        Ok(self.connection.write(Commands::MixTurnLeft, &speed)?)
     }
 
     pub fn forward_backward_mixed(&mut self, speed: u8) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::MixDrive, &[speed as u32], &[1])?)
+        self.check_power(Commands::MixDrive, speed)?;
+        Ok(self.connection.write(Commands::MixDrive, &speed)?)
     }
 
     pub fn left_right_mixed(&mut self, speed: u8) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::TurnLeftRight, &[speed as u32], &[1])?)
+        self.check_power(Commands::TurnLeftRight, speed)?;
+        Ok(self.connection.write(Commands::TurnLeftRight, &speed)?)
     }
 
+    /// Sets the serial timeout after which the RoboClaw stops both motors if no
+    /// valid packet was received. The timeout has a resolution of 100 ms and is
+    /// rounded up, so a non-zero timeout never disables it. The maximum is 25.5 s,
+    /// `Duration::ZERO` disables it.
+    pub fn set_serial_timeout(&mut self, timeout: Duration) -> Result<bool, RoboClawError> {
+        let tenths = serial_timeout_tenths(timeout).map_err(|error| {
+            error.into_error(Commands::SetSerialTimeout, self.connection.address)
+        })?;
+        Ok(self.connection.write(Commands::SetSerialTimeout, &tenths)?)
+    }
+
+    pub fn read_serial_timeout(&mut self) -> Result<Duration, RoboClawError> {
+        let tenths: u8 = self.connection.read(Commands::ReadSerialTimeout)?;
+        Ok(Duration::from_millis(tenths as u64 * 100))
+    }
+
+    #[deprecated(note = "use `Roboclaw::read_motor::<Encoder>(Motor::M1)`")]
     pub fn read_enc_m1(&mut self) -> Result<u32, &str> {
-        unimplemented!()
+        self.read_motor::<motor::Encoder>(Motor::M1)
+            .map(|encoder| encoder.count)
+            .map_err(|_| "failed to read the encoder")
     }
 
+    #[deprecated(note = "use `Roboclaw::read_motor::<Encoder>(Motor::M2)`")]
     pub fn read_enc_m2(&mut self) -> Result<u32, &str> {
-        unimplemented!()
+        self.read_motor::<motor::Encoder>(Motor::M2)
+            .map(|encoder| encoder.count)
+            .map_err(|_| "failed to read the encoder")
     }
 
-    pub fn set_enc_m1(&mut self, _value: i32) -> Result<(), &str> {
-        unimplemented!()
+    #[deprecated(note = "use `Roboclaw::send(Motor::M1, EncoderCount { .. })`")]
+    pub fn set_enc_m1(&mut self, value: i32) -> Result<(), &str> {
+        self.send(Motor::M1, motor::EncoderCount { count: value })
+            .map(|_| ())
+            .map_err(|_| "failed to set the encoder")
     }
 
-    pub fn set_enc_m2(&mut self, _value: i32) -> Result<(), &str> {
-        unimplemented!()
+    #[deprecated(note = "use `Roboclaw::send(Motor::M2, EncoderCount { .. })`")]
+    pub fn set_enc_m2(&mut self, value: i32) -> Result<(), &str> {
+        self.send(Motor::M2, motor::EncoderCount { count: value })
+            .map(|_| ())
+            .map_err(|_| "failed to set the encoder")
     }
 
     pub fn reset_encoders(&mut self) -> Result<bool, RoboClawError> {
-        Ok(self.connection.write(Commands::ResetEncoders, &[], &[])?)
+        Ok(self.connection.write(Commands::ResetEncoders, &())?)
     }
 
     pub fn read_main_battery_voltage(&mut self) -> Result<u32, RoboClawError> {
-        Ok(self.connection.read(Commands::ReadMainBatVoltage, &[2])?[0])
+        let voltage: u16 = self.connection.read(Commands::ReadMainBatVoltage)?;
+        Ok(voltage as u32)
     }
 
     pub fn read_logic_battery_voltage(&mut self) -> Result<u32, RoboClawError> {
-        Ok(self.connection.read(Commands::ReadLogicBatVoltage, &[2])?[0])
+        let voltage: u16 = self.connection.read(Commands::ReadLogicBatVoltage)?;
+        Ok(voltage as u32)
+    }
+
+    /// Reads the speeds of both motors in encoder counts per second, negative when
+    /// running backwards.
+    pub fn read_speeds(&mut self) -> Result<[i32; 2], RoboClawError> {
+        let [m1, m2] = self.read_each::<motor::Speed>()?;
+        Ok([m1.speed, m2.speed])
     }
 
+    /// Reads the currents of both motors in units of 10 mA.
+    pub fn read_currents(&mut self) -> Result<[i16; 2], RoboClawError> {
+        Ok(self.connection.read(Commands::ReadMotorCurrents)?)
+    }
+
+    /// Reads the board temperature in tenths of a degree Celsius.
+    pub fn read_temperature(&mut self) -> Result<u16, RoboClawError> {
+        Ok(self.connection.read(Commands::ReadTemperature1)?)
+    }
+
+    /// Reads the second temperature sensor in tenths of a degree Celsius. Only
+    /// fitted to some models.
+    pub fn read_temperature_2(&mut self) -> Result<u16, RoboClawError> {
+        self.require(Commands::ReadTemperature2)?;
+        Ok(self.connection.read(Commands::ReadTemperature2)?)
+    }
+
+    #[deprecated(note = "use `Roboclaw::send(Motor::M1, Duty { .. })`")]
     pub fn duty_m1(&mut self, duty: i16) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::M1DriveSignedDutyCycle, &[duty as u32], &[2])?)
+        self.send(Motor::M1, motor::Duty { duty })
     }
 
+    #[deprecated(note = "use `Roboclaw::send(Motor::M2, Duty { .. })`")]
     pub fn duty_m2(&mut self, duty: i16) -> Result<bool, RoboClawError> {
-        Ok(self
-            .connection
-            .write(Commands::M2DriveSignedDutyCycle, &[duty as u32], &[2])?)
+        self.send(Motor::M2, motor::Duty { duty })
     }
 
+    #[deprecated(note = "use `Roboclaw::send_each(Duty { .. }, Duty { .. })`")]
     pub fn duty_m1_m2(&mut self, duty1: i16, duty2: i16) -> Result<bool, RoboClawError> {
-        Ok(self.connection.write(
-            Commands::MixDriveSignedDutyCycle,
-            &[duty1 as u32, duty2 as u32],
-            &[2, 2],
-        )?)
+        self.send_each(motor::Duty { duty: duty1 }, motor::Duty { duty: duty2 })
     }
 
+    #[deprecated(note = "use `Roboclaw::send_each(Speed { .. }, Speed { .. })`")]
     pub fn speed_m1_m2(&mut self, speed_1: i32, speed_2: i32) -> Result<bool, RoboClawError> {
-        Ok(self.connection.write(
-            Commands::MixDriveSignedSpeed,
-            &[speed_1 as u32, speed_2 as u32],
-            &[4, 4],
-        )?)
+        self.send_each(
+            motor::Speed { speed: speed_1 },
+            motor::Speed { speed: speed_2 },
+        )
     }
 
+    #[deprecated(note = "use `Roboclaw::send(Motor::M1, SpeedDistance { .. })`")]
     pub fn speed_distance_m1(
         &mut self,
         speed: i32,
         distance: u32,
         execute_directly: bool,
     ) -> Result<bool, RoboClawError> {
-        Ok(self.connection.write(
-            Commands::M1DriveSignedSpeedDistanceBuffered,
-            &[speed as u32, distance as u32, execute_directly as u32],
-            &[4, 4, 1],
-        )?)
+        self.send(
+            Motor::M1,
+            motor::SpeedDistance {
+                speed,
+                distance,
+                execute_directly,
+            },
+        )
     }
 
+    #[deprecated(note = "use `Roboclaw::send(Motor::M2, SpeedDistance { .. })`")]
     pub fn speed_distance_m2(
         &mut self,
         speed: i32,
         distance: u32,
         execute_directly: bool,
     ) -> Result<bool, RoboClawError> {
-        Ok(self.connection.write(
-            Commands::M2DriveSignedSpeedDistanceBuffered,
-            &[speed as u32, distance as u32, execute_directly as u32],
-            &[4, 4, 1],
-        )?)
+        self.send(
+            Motor::M2,
+            motor::SpeedDistance {
+                speed,
+                distance,
+                execute_directly,
+            },
+        )
     }
 
+    #[deprecated(note = "use `Roboclaw::send_each(SpeedDistance { .. }, SpeedDistance { .. })`")]
     pub fn speed_distance_m1_m2(
         &mut self,
         speed_1: i32,
@@ -264,19 +856,23 @@ impl Roboclaw {
         distance_2: u32,
         execute_directly: bool,
     ) -> Result<bool, RoboClawError> {
-        Ok(self.connection.write(
-            Commands::MixDriveSignedSpeedDistanceBuffered,
-            &[
-                speed_1 as u32,
-                distance_1 as u32,
-                speed_2 as u32,
-                distance_2 as u32,
-                execute_directly as u32,
-            ],
-            &[4, 4, 4, 4, 1],
-        )?)
+        self.send_each(
+            motor::SpeedDistance {
+                speed: speed_1,
+                distance: distance_1,
+                execute_directly,
+            },
+            motor::SpeedDistance {
+                speed: speed_2,
+                distance: distance_2,
+                execute_directly,
+            },
+        )
     }
 
+    #[deprecated(
+        note = "use `Roboclaw::send_each(SpeedAccelDistance { .. }, SpeedAccelDistance { .. })`, which sends an acceleration per motor"
+    )]
     pub fn speed_accel_distance_m1_m2(
         &mut self,
         accel: u32,
@@ -286,35 +882,82 @@ impl Roboclaw {
         distance_2: u32,
         execute_directly: bool,
     ) -> Result<bool, RoboClawError> {
-        Ok(self.connection.write(
+        // `send_each` uses the command with an acceleration per motor, this one
+        // keeps sending the shared acceleration.
+        self.write_supported(
             Commands::MixDriveSignedSpeedAccelDistanceBuffered,
-            &[
-                accel as u32,
-                speed_1 as u32,
-                distance_1 as u32,
-                speed_2 as u32,
-                distance_2 as u32,
-                execute_directly as u32,
-            ],
-            &[4, 4, 4, 4, 4, 1],
-        )?)
+            &(
+                accel,
+                speed_1,
+                distance_1,
+                speed_2,
+                distance_2,
+                execute_directly,
+            ),
+        )
     }
 
     pub fn read_buffers(&mut self) -> Result<[BufferStatus; 2], RoboClawError> {
-        let values = self.connection.read(Commands::ReadBufferLength, &[1, 1])?;
+        let values: [u8; 2] = self.connection.read(Commands::ReadBufferLength)?;
         Ok(values.map(|data| match data {
             0x0 => BufferStatus::LastCommandExecuting,
             0x80 => BufferStatus::Empty,
-            num => BufferStatus::NotEmpty(num as u8),
+            num => BufferStatus::NotEmpty(num),
         }))
     }
 
     pub fn read_min_max_main_voltages(&mut self) -> Result<[u32; 2], RoboClawError> {
+        let voltages: [u16; 2] = self.connection.read(Commands::ReadMainBatVoltageSettings)?;
+        Ok(voltages.map(u32::from))
+    }
+
+    /// Sets the main battery voltages, in tenths of a volt, outside of which the
+    /// motors are stopped. `min` may not exceed `max`; the range the board is rated
+    /// for differs per model and is left to the RoboClaw.
+    pub fn set_main_battery_voltages(&mut self, min: u16, max: u16) -> Result<bool, RoboClawError> {
+        self.check(
+            Commands::SetMainBatVoltage,
+            "min",
+            min.into(),
+            0,
+            max.into(),
+        )?;
+        Ok(self
+            .connection
+            .write(Commands::SetMainBatVoltage, &(min, max))?)
+    }
+
+    /// Sets the logic battery voltages, in tenths of a volt, outside of which the
+    /// motors are stopped, see `set_main_battery_voltages`.
+    pub fn set_logic_battery_voltages(
+        &mut self,
+        min: u16,
+        max: u16,
+    ) -> Result<bool, RoboClawError> {
+        self.check(
+            Commands::SetLogicBatVoltage,
+            "min",
+            min.into(),
+            0,
+            max.into(),
+        )?;
         Ok(self
             .connection
-            .read(Commands::ReadMainBatVoltageSettings, &[2, 2])?)
+            .write(Commands::SetLogicBatVoltage, &(min, max))?)
     }
 
+    /// Reads the minimum and maximum logic battery voltages in tenths of a volt.
+    pub fn read_min_max_logic_voltages(&mut self) -> Result<[u32; 2], RoboClawError> {
+        let voltages: [u16; 2] = self
+            .connection
+            .read(Commands::ReadLogicBatVoltageSettings)?;
+        Ok(voltages.map(u32::from))
+    }
+
+    #[deprecated(
+        note = "use `Roboclaw::send_each(SpeedAccelDecelPosition { .. }, SpeedAccelDecelPosition { .. })`"
+    )]
+    #[allow(clippy::too_many_arguments)]
     pub fn speed_accel_deccel_position_m1_m2(
         &mut self,
         accel_1: u32,
@@ -327,36 +970,662 @@ impl Roboclaw {
         position_2: u32,
         execute_directly: bool,
     ) -> Result<bool, RoboClawError> {
-        Ok(self.connection.write(
-            Commands::MixDriveSpeedAccelDeccelPosition,
-            &[
-                accel_1 as u32,
-                speed_1 as u32,
-                deccel_1 as u32,
-                position_1 as u32,
-                accel_2 as u32,
-                speed_2 as u32,
-                deccel_2 as u32,
-                position_2 as u32,
-                execute_directly as u32,
-            ],
-            &[4, 4, 4, 4, 4, 4, 4, 4, 1],
-        )?)
+        // The wire format has an unsigned speed and a signed position.
+        let command = Commands::MixDriveSpeedAccelDeccelPosition;
+        let address = self.connection.address;
+        for (argument, value) in [
+            ("speed_1", speed_1 as i64),
+            ("speed_2", speed_2 as i64),
+            ("position_1", position_1 as i64),
+            ("position_2", position_2 as i64),
+        ] {
+            OutOfRange::check(argument, value, 0, i32::MAX as i64)
+                .map_err(|error| error.into_error(command, address))?;
+        }
+        self.send_each(
+            motor::SpeedAccelDecelPosition {
+                accel: accel_1,
+                speed: speed_1 as u32,
+                decel: deccel_1,
+                position: position_1 as i32,
+                execute_directly,
+            },
+            motor::SpeedAccelDecelPosition {
+                accel: accel_2,
+                speed: speed_2 as u32,
+                decel: deccel_2,
+                position: position_2 as i32,
+                execute_directly,
+            },
+        )
     }
 
     pub fn read_encoders(&mut self) -> Result<[u32; 2], RoboClawError> {
-        Ok(self.connection.read(Commands::ReadEncoderCounts, &[4, 4])?)
+        Ok(self.connection.read(Commands::ReadEncoderCounts)?)
     }
 
+    /// Reads the status flags. Older firmware reports a 16-bit status, newer firmware
+    /// the extended 32-bit one. The layout is chosen by the firmware version, or by
+    /// the length of the response if the firmware string cannot be parsed.
     pub fn read_error(&mut self) -> Result<StatusFlags, RoboClawError> {
-        let value = self.connection.read(Commands::ReadStatus, &[1])?;
-        Ok(value.map(|data| StatusFlags::from_bits(data as u16))[0].unwrap())
+        match self.capabilities() {
+            Ok(capabilities) if capabilities.firmware >= EXTENDED_FIRMWARE => Ok(
+                StatusFlags::from_extended(self.connection.read(Commands::ReadStatus)?),
+            ),
+            Ok(_) => Ok(StatusFlags::from_legacy(
+                self.connection.read(Commands::ReadStatus)?,
+            )),
+            Err(RoboClawError::UnknownFirmware { .. }) => {
+                let status = self.connection.read_sized(Commands::ReadStatus, 2, 4)?;
+                Ok(match *status {
+                    [high, low] => StatusFlags::from_legacy(u16::from_be_bytes([high, low])),
+                    [a, b, c, d] => StatusFlags::from_extended(u32::from_be_bytes([a, b, c, d])),
+                    _ => unreachable!("the status has 2 or 4 bytes"),
+                })
+            }
+            Err(error) => Err(error),
+        }
     }
 
     pub fn get_config(&mut self) -> Result<ConfigFlags, RoboClawError> {
-        let value = self
+        let value: u16 = self.connection.read(Commands::ReadStandardConfigSettings)?;
+        Ok(ConfigFlags::from_bits_truncate(value))
+    }
+
+    /// Sets the standard configuration read by `get_config`. `ConfigFlags` only
+    /// holds defined bits, so nothing else is checked.
+    pub fn set_config(&mut self, config: ConfigFlags) -> Result<bool, RoboClawError> {
+        Ok(self
+            .connection
+            .write(Commands::SetStandardConfigSettings, &config.bits())?)
+    }
+
+    /// Sets the modes of the S3, S4 and S5 pins. The modes are passed on unchecked,
+    /// as the valid modes differ per pin and firmware version.
+    pub fn set_s3_s4_s5_modes(&mut self, s3: u8, s4: u8, s5: u8) -> Result<bool, RoboClawError> {
+        Ok(self
+            .connection
+            .write(Commands::SetS3S4S5Modes, &(s3, s4, s5))?)
+    }
+
+    /// Reads the modes of the S3, S4 and S5 pins.
+    pub fn read_s3_s4_s5_modes(&mut self) -> Result<[u8; 3], RoboClawError> {
+        Ok(self.connection.read(Commands::ReadS3S4S5Modes)?)
+    }
+
+    /// Sets the RC/analog deadband for reverse and forward, in tenths of a percent
+    /// from 0 to 250.
+    pub fn set_deadband(&mut self, reverse: u8, forward: u8) -> Result<bool, RoboClawError> {
+        let command = Commands::SetDeadBandRCAnalog;
+        self.check(command, "reverse", reverse.into(), 0, 250)?;
+        self.check(command, "forward", forward.into(), 0, 250)?;
+        Ok(self.connection.write(command, &(reverse, forward))?)
+    }
+
+    /// Reads the RC/analog deadband for reverse and forward, in tenths of a percent.
+    pub fn read_deadband(&mut self) -> Result<[u8; 2], RoboClawError> {
+        Ok(self.connection.read(Commands::ReadDeadBandRCAnalog)?)
+    }
+
+    /// Reads the speeds of both motors measured over the last 1/300 s in one
+    /// exchange, see `motor::RawSpeed`.
+    pub fn read_raw_speeds(&mut self) -> Result<[i32; 2], RoboClawError> {
+        Ok(self.connection.read(Commands::ReadRawMotorSpeeds)?)
+    }
+
+    /// Reads the default duty cycle accelerations of both motors. The RoboClaw
+    /// reports them as 32-bit values, unlike the 16-bit `motor::DefaultDutyAccel`.
+    pub fn read_default_duty_accels(&mut self) -> Result<[u32; 2], RoboClawError> {
+        Ok(self.connection.read(Commands::ReadDefaultDutyCycleAccel)?)
+    }
+
+    /// Resets every setting to its factory default.
+    pub fn restore_defaults(&mut self) -> Result<bool, RoboClawError> {
+        Ok(self.connection.write(Commands::RestoreDefaults, &())?)
+    }
+
+    /// Stores the current settings in the EEPROM, so they survive a power cycle.
+    pub fn write_settings_to_eeprom(&mut self) -> Result<bool, RoboClawError> {
+        Ok(self.connection.write(Commands::EEPROMWriteSettings, &())?)
+    }
+
+    /// Replaces the current settings with those stored in the EEPROM.
+    pub fn read_settings_from_eeprom(&mut self) -> Result<bool, RoboClawError> {
+        Ok(self.connection.write(Commands::EEPROMReadSettings, &())?)
+    }
+
+    /// Sets the modes of the CTRL1 and CTRL2 outputs, fitted to some models only.
+    pub fn set_ctrl_modes(
+        &mut self,
+        ctrl1: CtrlMode,
+        ctrl2: CtrlMode,
+    ) -> Result<bool, RoboClawError> {
+        Ok(self
+            .connection
+            .write(Commands::SetCTRLModes, &(ctrl1 as u8, ctrl2 as u8))?)
+    }
+
+    /// Reads the modes of the CTRL1 and CTRL2 outputs.
+    pub fn read_ctrl_modes(&mut self) -> Result<[CtrlMode; 2], RoboClawError> {
+        let values: [u8; 2] = self.connection.read(Commands::ReadCTRLModes)?;
+        let mode = |value| match value {
+            0 => Ok(CtrlMode::Disabled),
+            1 => Ok(CtrlMode::User),
+            2 => Ok(CtrlMode::VoltageClamp),
+            3 => Ok(CtrlMode::Brake),
+            value => Err(RoboClawError::UnexpectedValue {
+                command: Commands::ReadCTRLModes,
+                address: self.connection.address,
+                value: value as u32,
+            }),
+        };
+        Ok([mode(values[0])?, mode(values[1])?])
+    }
+
+    /// Sets the value of the CTRL1 output. It is passed on unchecked, as its meaning
+    /// depends on the mode set with `set_ctrl_modes`.
+    pub fn set_ctrl1(&mut self, value: u16) -> Result<bool, RoboClawError> {
+        Ok(self.connection.write(Commands::SetCTRL1, &value)?)
+    }
+
+    /// Sets the value of the CTRL2 output, see `set_ctrl1`.
+    pub fn set_ctrl2(&mut self, value: u16) -> Result<bool, RoboClawError> {
+        Ok(self.connection.write(Commands::SetCTRL2, &value)?)
+    }
+
+    /// Reads the values of the CTRL1 and CTRL2 outputs.
+    pub fn read_ctrls(&mut self) -> Result<[u16; 2], RoboClawError> {
+        Ok(self.connection.read(Commands::ReadCTRLs)?)
+    }
+
+    /// Sets the speed error, in counts per second, at which the motors are stopped.
+    /// The limits are not range checked: command 109 ("Set Speed Error Limits") of
+    /// the RoboClaw user manual gives no range beyond the 32-bit field, so every
+    /// `u32` is sent as is.
+    pub fn set_speed_error_limits(
+        &mut self,
+        limit_m1: u32,
+        limit_m2: u32,
+    ) -> Result<bool, RoboClawError> {
+        Ok(self
+            .connection
+            .write(Commands::SetSpeedErrorLimits, &(limit_m1, limit_m2))?)
+    }
+
+    pub fn read_speed_error_limits(&mut self) -> Result<[u32; 2], RoboClawError> {
+        Ok(self.connection.read(Commands::ReadSpeedErrorLimits)?)
+    }
+
+    pub fn read_speed_errors(&mut self) -> Result<[i32; 2], RoboClawError> {
+        Ok(self.connection.read(Commands::ReadSpeedErrors)?)
+    }
+
+    /// Sets the position error, in counts, at which the motors are stopped. The
+    /// limits are not range checked: command 112 ("Set Position Error Limits") of
+    /// the RoboClaw user manual gives no range beyond the 32-bit field, so every
+    /// `u32` is sent as is.
+    pub fn set_position_error_limits(
+        &mut self,
+        limit_m1: u32,
+        limit_m2: u32,
+    ) -> Result<bool, RoboClawError> {
+        Ok(self
             .connection
-            .read(Commands::ReadStandardConfigSettings, &[1])?;
-        Ok(value.map(|data| ConfigFlags::from_bits(data as u16))[0].unwrap())
+            .write(Commands::SetPositionErrorLimits, &(limit_m1, limit_m2))?)
+    }
+
+    pub fn read_position_error_limits(&mut self) -> Result<[u32; 2], RoboClawError> {
+        Ok(self.connection.read(Commands::ReadPositionErrorLimits)?)
+    }
+
+    pub fn read_position_errors(&mut self) -> Result<[i32; 2], RoboClawError> {
+        Ok(self.connection.read(Commands::ReadPositionErrors)?)
+    }
+
+    pub fn set_battery_voltage_offsets(
+        &mut self,
+        main_offset: i8,
+        logic_offset: i8,
+    ) -> Result<bool, RoboClawError> {
+        Ok(self.connection.write(
+            Commands::SetBatteryVoltageOffsets,
+            &(main_offset, logic_offset),
+        )?)
+    }
+
+    pub fn read_battery_voltage_offsets(&mut self) -> Result<[i8; 2], RoboClawError> {
+        Ok(self.connection.read(Commands::ReadBatteryVoltageOffsets)?)
+    }
+
+    /// Calibrates the main battery voltage offset against a reference `measured`
+    /// voltage (in tenths of a volt, e.g. from a multimeter) and writes it to the
+    /// controller. The logic battery offset is left untouched.
+    /// Returns the offsets now stored on the controller. Fails without writing if the
+    /// offset needed is outside -128..=127.
+    pub fn calibrate_main_battery_voltage(
+        &mut self,
+        measured: u32,
+    ) -> Result<[i8; 2], RoboClawError> {
+        let [main_offset, logic_offset] = self.read_battery_voltage_offsets()?;
+        let reported = self.read_main_battery_voltage()?;
+        let main_offset = calibrated_offset("main_offset", main_offset, measured, reported)
+            .map_err(|error| {
+                error.into_error(Commands::SetBatteryVoltageOffsets, self.connection.address)
+            })?;
+        self.set_battery_voltage_offsets(main_offset, logic_offset)?;
+        Ok([main_offset, logic_offset])
+    }
+
+    /// Calibrates the logic battery voltage offset against a reference `measured`
+    /// voltage (in tenths of a volt) and writes it to the controller. The main
+    /// battery offset is left untouched.
+    /// Returns the offsets now stored on the controller. Fails without writing if the
+    /// offset needed is outside -128..=127.
+    pub fn calibrate_logic_battery_voltage(
+        &mut self,
+        measured: u32,
+    ) -> Result<[i8; 2], RoboClawError> {
+        let [main_offset, logic_offset] = self.read_battery_voltage_offsets()?;
+        let reported = self.read_logic_battery_voltage()?;
+        let logic_offset = calibrated_offset("logic_offset", logic_offset, measured, reported)
+            .map_err(|error| {
+                error.into_error(Commands::SetBatteryVoltageOffsets, self.connection.address)
+            })?;
+        self.set_battery_voltage_offsets(main_offset, logic_offset)?;
+        Ok([main_offset, logic_offset])
+    }
+
+    /// Sets the share of each PWM period in which the current is not measured, in
+    /// hundredths of a percent from 0 to 10000.
+    pub fn set_current_blanking_percentages(
+        &mut self,
+        blanking_m1: u16,
+        blanking_m2: u16,
+    ) -> Result<bool, RoboClawError> {
+        let command = Commands::SetCurrentBlankingPercentages;
+        self.check(command, "blanking_m1", blanking_m1 as i64, 0, 10000)?;
+        self.check(command, "blanking_m2", blanking_m2 as i64, 0, 10000)?;
+        Ok(self.connection.write(
+            Commands::SetCurrentBlankingPercentages,
+            &(blanking_m1, blanking_m2),
+        )?)
+    }
+
+    pub fn read_current_blanking_percentages(&mut self) -> Result<[u16; 2], RoboClawError> {
+        Ok(self
+            .connection
+            .read(Commands::ReadCurrentBlankinPercentages)?)
+    }
+
+    pub fn set_pwm_mode(&mut self, mode: PwmMode) -> Result<bool, RoboClawError> {
+        Ok(self.connection.write(Commands::SetPWMMode, &(mode as u8))?)
+    }
+
+    pub fn read_pwm_mode(&mut self) -> Result<PwmMode, RoboClawError> {
+        let value: u8 = self.connection.read(Commands::ReadPWMMode)?;
+        match value {
+            0 => Ok(PwmMode::LockedAntiphase),
+            1 => Ok(PwmMode::SignMagnitude),
+            value => Err(RoboClawError::UnexpectedValue {
+                command: Commands::ReadPWMMode,
+                address: self.connection.address,
+                value: value as u32,
+            }),
+        }
+    }
+
+    /// Reads the 16-bit value stored at `location` of the user EEPROM, from 0 to
+    /// `MAX_USER_MEMORY_LOCATION`.
+    pub fn read_user_memory(&mut self, location: u8) -> Result<u16, RoboClawError> {
+        let command = Commands::EEPROMReadUserMemoryLocation;
+        check_user_memory_location(location)
+            .map_err(|error| error.into_error(command, self.connection.address))?;
+        Ok(self.connection.query(command, &location)?)
+    }
+
+    /// Writes `value` to `location` of the user EEPROM, from 0 to
+    /// `MAX_USER_MEMORY_LOCATION`, e.g. to tag a controller for
+    /// `Roboclaw::open_by_tag`.
+    pub fn write_user_memory(&mut self, location: u8, value: u16) -> Result<bool, RoboClawError> {
+        let command = Commands::EEPROMWriteUserMemoryLocation;
+        check_user_memory_location(location)
+            .map_err(|error| error.into_error(command, self.connection.address))?;
+        Ok(self.connection.write(command, &(location, value))?)
+    }
+}
+
+/// Checks that `location` lies within the user EEPROM.
+fn check_user_memory_location(location: u8) -> Result<(), OutOfRange> {
+    OutOfRange::check(
+        "location",
+        location.into(),
+        0,
+        MAX_USER_MEMORY_LOCATION.into(),
+    )
+}
+
+/// Converts a serial timeout to the controller's tenths of a second, rounding up.
+fn serial_timeout_tenths(timeout: Duration) -> Result<u8, OutOfRange> {
+    let tenths = timeout.as_nanos().div_ceil(100_000_000);
+    if tenths > u8::MAX as u128 {
+        return Err(OutOfRange {
+            argument: "timeout",
+            value: timeout.as_millis().min(i64::MAX as u128) as i64,
+            min: 0,
+            max: u8::MAX as i64 * 100,
+        });
+    }
+    Ok(tenths as u8)
+}
+
+/// Computes the battery voltage offset that makes the controller report `measured`
+/// when it currently reports `reported` with `current_offset` applied. Fails if the
+/// offset does not fit the controller's signed byte.
+fn calibrated_offset(
+    argument: &'static str,
+    current_offset: i8,
+    measured: u32,
+    reported: u32,
+) -> Result<i8, OutOfRange> {
+    let offset = current_offset as i64 + measured as i64 - reported as i64;
+    OutOfRange::check(argument, offset, i8::MIN.into(), i8::MAX.into())?;
+    Ok(offset as i8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use replay::{
+        tests::{ack, read, write, ADDRESS},
+        ReplayTransport,
+    };
+
+    #[test]
+    fn main_voltage_levels_are_validated() {
+        let frames = vec![
+            write(Commands::SetMinMainVoltage, &[140]),
+            ack(Commands::SetMinMainVoltage),
+            write(Commands::SetMaxMainVoltage, &[30]),
+            ack(Commands::SetMaxMainVoltage),
+        ];
+        let transport = ReplayTransport::from_frames(frames);
+        let handle = transport.handle();
+        let mut roboclaw = Roboclaw::from_transport(transport, ADDRESS).unwrap();
+
+        assert!(roboclaw.set_min_main_voltage(140).unwrap());
+        assert!(roboclaw.set_max_main_voltage(30).unwrap());
+        // Out of range levels fail before anything is sent.
+        assert!(matches!(
+            roboclaw.set_min_main_voltage(141),
+            Err(RoboClawError::InvalidArgument { value: 141, .. })
+        ));
+        assert!(matches!(
+            roboclaw.set_max_main_voltage(29),
+            Err(RoboClawError::InvalidArgument { min: 30, .. })
+        ));
+        handle.assert_finished();
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_shims_keep_their_commands() {
+        let mut frames = vec![
+            write(Commands::SetMinMainVoltage, &[10]),
+            ack(Commands::SetMinMainVoltage),
+        ];
+        // Buffered moves are preceded by a read of the buffers, see `Connection::write`.
+        frames.extend(read(Commands::ReadBufferLength, &[0x80, 0x80]));
+        frames.extend([
+            write(
+                Commands::MixDriveSignedSpeedAccelDistanceBuffered,
+                &[
+                    0, 0, 3, 232, 255, 255, 254, 12, 0, 0, 0, 100, 0, 0, 1, 244, 0, 0, 0, 200, 1,
+                ],
+            ),
+            ack(Commands::MixDriveSignedSpeedAccelDistanceBuffered),
+        ]);
+        let transport = ReplayTransport::from_frames(frames);
+        let handle = transport.handle();
+        let mut roboclaw = Roboclaw::from_transport(transport, ADDRESS).unwrap();
+
+        assert!(roboclaw.set_min_voltage_main_battery(10).unwrap());
+        assert!(roboclaw
+            .speed_accel_distance_m1_m2(1000, -500, 100, 500, 200, true)
+            .unwrap());
+        handle.assert_finished();
+    }
+
+    #[test]
+    fn settings_are_validated_before_sending() {
+        let transport = ReplayTransport::from_frames(Vec::new());
+        let handle = transport.handle();
+        let mut roboclaw = Roboclaw::from_transport(transport, ADDRESS).unwrap();
+
+        assert!(roboclaw.set_current_blanking_percentages(0, 10001).is_err());
+        assert!(matches!(
+            roboclaw.send(Motor::M1, motor::EncoderMode { mode: 0x02 }),
+            Err(RoboClawError::InvalidArgument { value: 2, .. })
+        ));
+        assert!(roboclaw
+            .send(Motor::M2, motor::MaxCurrent { max: 100, min: 200 })
+            .is_err());
+        assert!(roboclaw.set_min_logic_voltage(141).is_err());
+        assert!(roboclaw.set_max_logic_voltage(29).is_err());
+        assert!(matches!(
+            roboclaw.set_main_battery_voltages(300, 120),
+            Err(RoboClawError::InvalidArgument { max: 120, .. })
+        ));
+        assert!(roboclaw.set_logic_battery_voltages(61, 60).is_err());
+        assert!(matches!(
+            roboclaw.read_user_memory(MAX_USER_MEMORY_LOCATION + 1),
+            Err(RoboClawError::InvalidArgument {
+                command: Commands::EEPROMReadUserMemoryLocation,
+                value: 128,
+                ..
+            })
+        ));
+        assert!(roboclaw.write_user_memory(255, 0x1234).is_err());
+        assert!(matches!(
+            roboclaw.set_deadband(25, 251),
+            Err(RoboClawError::InvalidArgument {
+                argument: "forward",
+                ..
+            })
+        ));
+        handle.assert_finished();
+    }
+
+    #[test]
+    fn shared_readings_take_one_exchange() {
+        let mut frames = read(
+            Commands::ReadAutoHomeSettings,
+            &[0, 100, 0, 0, 0x27, 0x10, 0x7F, 0xFF, 0, 0, 0, 0],
+        )
+        .to_vec();
+        frames.extend(read(Commands::ReadMotorPWMs, &[0x40, 0x00, 0xC0, 0x00]));
+        frames.extend(read(Commands::ReadEncoderModes, &[0x01, 0x20]));
+        let transport = ReplayTransport::from_frames(frames);
+        let handle = transport.handle();
+        let mut roboclaw = Roboclaw::from_transport(transport, ADDRESS).unwrap();
+
+        assert_eq!(
+            roboclaw.read_each::<motor::AutoHome>().unwrap(),
+            [
+                motor::AutoHome {
+                    percentage: 100,
+                    timeout: 10_000,
+                },
+                motor::AutoHome {
+                    percentage: 32767,
+                    timeout: 0,
+                },
+            ]
+        );
+        assert_eq!(
+            roboclaw.read_motor::<motor::Pwm>(Motor::M2).unwrap(),
+            motor::Pwm { duty: -16384 }
+        );
+        assert_eq!(
+            roboclaw
+                .read_motor::<motor::EncoderMode>(Motor::M1)
+                .unwrap(),
+            motor::EncoderMode { mode: 0x01 }
+        );
+        handle.assert_finished();
+    }
+
+    #[test]
+    fn board_settings_are_written_and_read() {
+        let mut frames = vec![
+            write(Commands::SetLogicBatVoltage, &[0, 60, 1, 44]),
+            ack(Commands::SetLogicBatVoltage),
+            write(Commands::SetDeadBandRCAnalog, &[25, 250]),
+            ack(Commands::SetDeadBandRCAnalog),
+            write(Commands::SetCTRLModes, &[3, 1]),
+            ack(Commands::SetCTRLModes),
+            write(Commands::EEPROMWriteSettings, &[]),
+            ack(Commands::EEPROMWriteSettings),
+            write(Commands::EEPROMWriteUserMemoryLocation, &[127, 0x12, 0x34]),
+            ack(Commands::EEPROMWriteUserMemoryLocation),
+        ];
+        frames.extend(read(Commands::ReadCTRLModes, &[2, 0]));
+        frames.extend(read(Commands::ReadCTRLModes, &[4, 0]));
+        frames.extend(read(
+            Commands::ReadRawMotorSpeeds,
+            &[0, 0, 0, 10, 0xFF, 0xFF, 0xFF, 0xF6],
+        ));
+        let transport = ReplayTransport::from_frames(frames);
+        let handle = transport.handle();
+        let mut roboclaw = Roboclaw::from_transport(transport, ADDRESS).unwrap();
+
+        assert!(roboclaw.set_logic_battery_voltages(60, 300).unwrap());
+        assert!(roboclaw.set_deadband(25, 250).unwrap());
+        assert!(roboclaw
+            .set_ctrl_modes(CtrlMode::Brake, CtrlMode::User)
+            .unwrap());
+        assert!(roboclaw.write_settings_to_eeprom().unwrap());
+        assert!(roboclaw
+            .write_user_memory(MAX_USER_MEMORY_LOCATION, 0x1234)
+            .unwrap());
+        assert_eq!(
+            roboclaw.read_ctrl_modes().unwrap(),
+            [CtrlMode::VoltageClamp, CtrlMode::Disabled]
+        );
+        assert!(matches!(
+            roboclaw.read_ctrl_modes(),
+            Err(RoboClawError::UnexpectedValue { value: 4, .. })
+        ));
+        assert_eq!(roboclaw.read_raw_speeds().unwrap(), [10, -10]);
+        handle.assert_finished();
+    }
+
+    #[test]
+    fn calibrated_offset_corrects_the_reported_voltage() {
+        assert_eq!(calibrated_offset("main_offset", 0, 240, 236), Ok(4));
+        assert_eq!(calibrated_offset("main_offset", 4, 240, 244), Ok(0));
+        assert_eq!(calibrated_offset("main_offset", -3, 120, 125), Ok(-8));
+        assert_eq!(calibrated_offset("main_offset", 120, 247, 240), Ok(127));
+        assert_eq!(calibrated_offset("main_offset", -120, 232, 240), Ok(-128));
+    }
+
+    #[test]
+    fn calibrated_offset_rejects_offsets_outside_a_byte() {
+        assert_eq!(
+            calibrated_offset("logic_offset", 120, 248, 240),
+            Err(OutOfRange {
+                argument: "logic_offset",
+                value: 128,
+                min: -128,
+                max: 127,
+            })
+        );
+        assert_eq!(
+            calibrated_offset("main_offset", 0, 0, 500).map_err(|error| error.value),
+            Err(-500)
+        );
+    }
+
+    #[test]
+    fn serial_timeout_rounds_up_to_tenths() {
+        assert_eq!(serial_timeout_tenths(Duration::ZERO), Ok(0));
+        assert_eq!(serial_timeout_tenths(Duration::from_nanos(1)), Ok(1));
+        assert_eq!(serial_timeout_tenths(Duration::from_millis(40)), Ok(1));
+        assert_eq!(serial_timeout_tenths(Duration::from_millis(100)), Ok(1));
+        assert_eq!(serial_timeout_tenths(Duration::from_millis(101)), Ok(2));
+        assert_eq!(
+            serial_timeout_tenths(Duration::from_millis(25_500)),
+            Ok(255)
+        );
+        assert_eq!(
+            serial_timeout_tenths(Duration::from_millis(25_501)).map_err(|error| error.value),
+            Err(25_501)
+        );
+    }
+
+    #[test]
+    fn legacy_status_maps_each_bit() {
+        assert_eq!(StatusFlags::from_legacy(0x0000), StatusFlags::NORMAL);
+        assert_eq!(
+            StatusFlags::from_legacy(0x0001),
+            StatusFlags::M1_OVERCURRENT_WARNING
+        );
+        assert_eq!(StatusFlags::from_legacy(0x0004), StatusFlags::E_STOP);
+        assert_eq!(
+            StatusFlags::from_legacy(0x0100),
+            StatusFlags::M1_DRIVER_FAULT
+        );
+        assert_eq!(
+            StatusFlags::from_legacy(0xC000),
+            StatusFlags::M1_HOME | StatusFlags::M2_HOME
+        );
+        let all = StatusFlags::from_legacy(0xFFFF);
+        assert_eq!(all.bits().count_ones(), 16);
+        assert!(
+            !all.intersects(StatusFlags::M1_SPEED_ERROR | StatusFlags::SPEED_ERROR_LIMIT_WARNING)
+        );
+    }
+
+    #[test]
+    fn extended_status_uses_its_own_layout() {
+        assert_eq!(StatusFlags::from_extended(0x0000_0001), StatusFlags::E_STOP);
+        assert_eq!(
+            StatusFlags::from_extended(0x0000_0100),
+            StatusFlags::M1_SPEED_ERROR
+        );
+        assert_eq!(
+            StatusFlags::from_extended(0x0001_0000),
+            StatusFlags::M1_OVERCURRENT_WARNING
+        );
+        assert_eq!(
+            StatusFlags::from_extended(0x0300_0000),
+            StatusFlags::SPEED_ERROR_LIMIT_WARNING | StatusFlags::POSITION_ERROR_LIMIT_WARNING
+        );
+        // Home bits and undefined bits are not part of the extended layout.
+        assert_eq!(StatusFlags::from_extended(0xFC00_C000), StatusFlags::NORMAL);
+    }
+
+    #[test]
+    fn status_of_unknown_firmware_is_decoded_by_length() {
+        // The unparsable firmware string is read once and cached.
+        let mut frames = read(Commands::ReadFirmwareVersion, b"Custom RoboClaw build\n\0").to_vec();
+        frames.extend(read(Commands::ReadStatus, &[0x00, 0x01]));
+        frames.extend(read(Commands::ReadStatus, &[0x01, 0x00, 0x01, 0x00]));
+        let transport = ReplayTransport::from_frames(frames);
+        let handle = transport.handle();
+        let mut roboclaw = Roboclaw::from_transport(transport, ADDRESS).unwrap();
+
+        assert_eq!(
+            roboclaw.read_error().unwrap(),
+            StatusFlags::M1_OVERCURRENT_WARNING
+        );
+        assert_eq!(
+            roboclaw.read_error().unwrap(),
+            StatusFlags::M1_SPEED_ERROR | StatusFlags::SPEED_ERROR_LIMIT_WARNING
+        );
+        assert!(matches!(
+            roboclaw.firmware_version(),
+            Err(RoboClawError::UnknownFirmware { .. })
+        ));
+        handle.assert_finished();
     }
 }
@@ -0,0 +1,698 @@
+//! This module provides the typed, motor-generic command API.
+//!
+//! Instead of one method per motor and command (`duty_m1`, `duty_m2`,
+//! `duty_m1_m2`, ...), a `Motor` selector picks the command variant and the
+//! arguments are passed as a struct:
+//!
+//! ```no_run
+//! # fn main() -> Result<(), roboclaw::errors::RoboClawError> {
+//! use roboclaw::motor::{Duty, Motor, SpeedAccelDecelPosition, VelocityPid};
+//! use roboclaw::Roboclaw;
+//!
+//! let mut roboclaw = Roboclaw::builder().port("/dev/ttyACM0").build()?;
+//! roboclaw.send(Motor::Both, Duty { duty: 8192 })?;
+//! roboclaw.send_each(Duty { duty: 8192 }, Duty { duty: -8192 })?;
+//! roboclaw.send(
+//!     Motor::M1,
+//!     SpeedAccelDecelPosition {
+//!         accel: 2000,
+//!         speed: 4000,
+//!         decel: 2000,
+//!         position: 10_000,
+//!         execute_directly: true,
+//!     },
+//! )?;
+//! let pid: VelocityPid = roboclaw.read_motor(Motor::M2)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every command that exists once per motor has an argument struct implementing
+//! `MotorCommand`, and every per-motor read a result struct implementing
+//! `MotorReading`. Where the RoboClaw has a command driving both motors in one
+//! packet, `Motor::Both` and `Roboclaw::send_each` use it, so both motors change
+//! at the same time. Otherwise the command is sent to M1 and then to M2. Arguments
+//! are checked with `MotorCommand::validate` before anything is sent.
+//!
+//! Some settings are read for both motors in one response, e.g. `AutoHome` or
+//! `EncoderMode`; `Roboclaw::read_each` then takes a single exchange. Two such
+//! reads are methods of `Roboclaw` instead, as their responses do not match a
+//! per-motor type: `Roboclaw::read_raw_speeds`, as `RawSpeed` is read with the
+//! per-motor commands, and `Roboclaw::read_default_duty_accels`, which reports
+//! wider values than `DefaultDutyAccel` sets. Settings of the whole board, such as
+//! the battery voltages or the CTRL outputs, are methods of `Roboclaw` too.
+//!
+//! The mixed commands sharing one acceleration between both motors,
+//! `MixDriveSignedSpeedAccel` and `MixDriveSignedSpeedAccelDistanceBuffered`, have
+//! no argument struct: `send_each` with the same acceleration for both motors does
+//! the same with the commands taking an acceleration per motor.
+//!
+//! Speeds are in encoder counts per second, accelerations in counts per second
+//! squared, distances and positions in encoder counts. A duty cycle of ±32767 is
+//! full power.
+
+use crate::{
+    capabilities::Capabilities,
+    codec::{Decode, Encode, WireRead},
+    errors::{ConnectionError, OutOfRange},
+    Commands,
+};
+
+/// Selects the motor channel a command is sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Motor {
+    M1,   // Motor channel 1
+    M2,   // Motor channel 2
+    Both, // Both channels, with the same arguments
+}
+
+/// The arguments of a command that exists once per motor.
+pub trait MotorCommand: Encode {
+    /// The commands for M1 and M2.
+    const COMMANDS: [Commands; 2];
+    /// The command for both motors in one packet, if the RoboClaw has one.
+    const BOTH: Option<Commands> = None;
+
+    /// Encodes the arguments of both motors into the packet of the `BOTH` command.
+    /// Defaults to the arguments of M1 followed by those of M2.
+    fn encode_both(m1: &Self, m2: &Self, buffer: &mut Vec<u8>) {
+        m1.encode(buffer);
+        m2.encode(buffer);
+    }
+
+    /// Checks the arguments against the ranges documented for the command.
+    /// Defaults to accepting every value of the argument types.
+    fn validate(&self) -> Result<(), OutOfRange> {
+        Ok(())
+    }
+
+    /// Checks the arguments against the `capabilities` of the connected model, once
+    /// they are known. Defaults to accepting every value.
+    fn validate_for(&self, _capabilities: &Capabilities) -> Result<(), OutOfRange> {
+        Ok(())
+    }
+}
+
+/// The result of a read that exists once per motor.
+pub trait MotorReading: Decode {
+    /// The read commands for M1 and M2.
+    const COMMANDS: [Commands; 2];
+    /// Whether a single command answers for both motors, M1 first. Both
+    /// `COMMANDS` are then that command.
+    const SHARED: bool = false;
+}
+
+/// Returns the command that carries the arguments of motor `index` (0 for M1, 1
+/// for M2) when `C` is sent to `motor`. Argument errors name this command.
+pub(crate) fn carrier<C: MotorCommand>(motor: Motor, index: usize) -> Commands {
+    match motor {
+        Motor::Both => C::BOTH.unwrap_or(C::COMMANDS[index]),
+        _ => C::COMMANDS[index],
+    }
+}
+
+/// The arguments of both motors, encoded for the `BOTH` command of `C`.
+pub(crate) struct Both<'a, C>(pub &'a C, pub &'a C);
+
+impl<C: MotorCommand> Encode for Both<'_, C> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        C::encode_both(self.0, self.1, buffer)
+    }
+}
+
+/// Drives forward with a power of 0 (stopped) to 127 (full power). For `Motor::Both`
+/// two packets are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Forward {
+    pub speed: u8, // Power, 0 to 127
+}
+
+/// Drives backward with a power of 0 (stopped) to 127 (full power). For
+/// `Motor::Both` two packets are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backward {
+    pub speed: u8, // Power, 0 to 127
+}
+
+/// Drives with a 7-bit power: 0 is full backward, 64 stopped and 127 full forward.
+/// For `Motor::Both` two packets are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForwardBackward {
+    pub speed: u8, // Power, 0 to 127 with 64 stopped
+}
+
+/// Drives with a signed duty cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Duty {
+    pub duty: i16, // Duty cycle, ±32767 is full power
+}
+
+/// Ramps to a signed duty cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DutyAccel {
+    pub duty: i16,  // Duty cycle, ±32767 is full power
+    pub accel: u32, // Duty cycle change per second
+}
+
+/// Drives at a signed speed with the speed PID. Also read back as the current
+/// speed of a motor. Every speed is accepted: the useful limit is the `qpps` of
+/// the velocity PID, which is set per motor and not known here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Speed {
+    pub speed: i32, // Speed in counts per second, negative backwards
+}
+
+/// Accelerates to a signed speed. Every speed and acceleration is accepted, see
+/// `Speed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeedAccel {
+    pub accel: u32, // Acceleration in counts per second squared
+    pub speed: i32, // Speed in counts per second, negative backwards
+}
+
+/// Drives a distance at a signed speed. For `Motor::Both` the buffer flag of M1
+/// applies to both motors. Every speed and distance is accepted, see `Speed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeedDistance {
+    pub speed: i32,             // Speed in counts per second, negative backwards
+    pub distance: u32,          // Distance in counts
+    pub execute_directly: bool, // Replaces the buffered moves instead of queueing
+}
+
+/// Accelerates to a signed speed and drives a distance. For `Motor::Both` the
+/// buffer flag of M1 applies to both motors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeedAccelDistance {
+    pub accel: u32,             // Acceleration in counts per second squared
+    pub speed: i32,             // Speed in counts per second, negative backwards
+    pub distance: u32,          // Distance in counts
+    pub execute_directly: bool, // Replaces the buffered moves instead of queueing
+}
+
+/// Moves to a position with a trapezoidal speed profile. For `Motor::Both` the
+/// buffer flag of M1 applies to both motors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeedAccelDecelPosition {
+    pub accel: u32,             // Acceleration in counts per second squared
+    pub speed: u32,             // Cruise speed in counts per second
+    pub decel: u32,             // Deceleration in counts per second squared
+    pub position: i32,          // Target position in counts
+    pub execute_directly: bool, // Replaces the buffered moves instead of queueing
+}
+
+/// Moves to a position at a speed. For `Motor::Both` the buffer flag of M1 applies
+/// to both motors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeedPosition {
+    pub speed: u32,             // Speed in counts per second
+    pub position: i32,          // Target position in counts
+    pub execute_directly: bool, // Replaces the buffered moves instead of queueing
+}
+
+/// Moves to a position with the default speed and accelerations. For
+/// `Motor::Both` the buffer flag of M1 applies to both motors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub position: i32,          // Target position in counts
+    pub execute_directly: bool, // Replaces the buffered moves instead of queueing
+}
+
+/// Sets the encoder count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderCount {
+    pub count: i32, // New encoder count
+}
+
+/// The constants of the velocity PID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VelocityPid {
+    pub p: u32,    // Proportional constant, fixed point with 16 fraction bits
+    pub i: u32,    // Integral constant, fixed point with 16 fraction bits
+    pub d: u32,    // Derivative constant, fixed point with 16 fraction bits
+    pub qpps: u32, // Speed at full power in counts per second
+}
+
+/// The constants of the position PID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PositionPid {
+    pub p: u32,        // Proportional constant, fixed point with 10 fraction bits
+    pub i: u32,        // Integral constant, fixed point with 10 fraction bits
+    pub d: u32,        // Derivative constant, fixed point with 10 fraction bits
+    pub max_i: u32,    // Windup limit of the integral term
+    pub deadzone: u32, // Position error ignored, in counts
+    pub min: i32,      // Lowest position in counts
+    pub max: i32,      // Highest position in counts
+}
+
+/// Sets the default acceleration of duty cycle commands without one. Every `u16`
+/// lies below the largest duty cycle acceleration, so none is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultDutyAccel {
+    pub accel: u16, // Duty cycle change per second
+}
+
+/// Sets the default speed of position commands without one. Every speed is
+/// accepted, see `Speed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultSpeed {
+    pub speed: u16, // Speed in counts per second
+}
+
+/// Sets the encoder mode: absolute instead of quadrature (bit 0), reversed motor
+/// (bit 5), reversed encoder (bit 6) and RC/analog encoder support (bit 7). The
+/// other bits are undefined and rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderMode {
+    pub mode: u8, // Encoder mode bits
+}
+
+/// Sets the duty cycle and timeout of the homing run at startup. The duty cycle
+/// is at most 32767, every timeout is accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoHome {
+    pub percentage: u16, // Duty cycle of the homing run
+    pub timeout: u32,    // Time until homing gives up
+}
+
+/// The current limits. The lowest current may not exceed the highest, which in
+/// turn may not exceed the peak current of the connected model, once known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxCurrent {
+    pub max: u32, // Highest current in units of 10 mA
+    pub min: u32, // Lowest (regenerative) current in units of 10 mA
+}
+
+/// The encoder count of a motor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Encoder {
+    pub count: u32, // Encoder count
+    pub status: u8, // Underflow (bit 0), backwards (bit 1) and overflow (bit 2) flags
+}
+
+/// The speed of a motor, measured over the last 1/300 s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawSpeed {
+    pub speed: i32, // Speed in counts per 1/300 s, negative backwards
+}
+
+/// The speed of a motor, averaged by the RoboClaw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AvgSpeed {
+    pub speed: i32, // Speed in counts per second, negative backwards
+}
+
+/// The PWM output driving a motor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pwm {
+    pub duty: i16, // Duty cycle, ±32767 is full power
+}
+
+/// The largest duty cycle, full power in either direction.
+const MAX_DUTY: i64 = 32767;
+/// The largest duty cycle acceleration.
+const MAX_DUTY_ACCEL: i64 = 655_359;
+/// The largest power of the 7-bit commands.
+const MAX_POWER: i64 = 127;
+/// The defined bits of `EncoderMode`.
+const ENCODER_MODE_BITS: u8 = 0xE1;
+
+macro_rules! motor_command {
+    (
+        $ty:ident,
+        [$m1:ident, $m2:ident],
+        $both:expr,
+        |$this:ident| $args:expr
+        $(, validate |$valid:ident| $check:expr)?
+    ) => {
+        impl Encode for $ty {
+            fn encode(&self, buffer: &mut Vec<u8>) {
+                let $this = self;
+                $args.encode(buffer)
+            }
+        }
+
+        impl MotorCommand for $ty {
+            const COMMANDS: [Commands; 2] = [Commands::$m1, Commands::$m2];
+            const BOTH: Option<Commands> = $both;
+
+            $(
+                fn validate(&self) -> Result<(), OutOfRange> {
+                    let $valid = self;
+                    $check
+                }
+            )?
+        }
+    };
+}
+
+motor_command!(
+    Forward,
+    [M1Forward, M2Forward],
+    None,
+    |this| this.speed,
+    validate | this | OutOfRange::check("speed", this.speed as i64, 0, MAX_POWER)
+);
+motor_command!(
+    Backward,
+    [M1Backward, M2Backward],
+    None,
+    |this| this.speed,
+    validate | this | OutOfRange::check("speed", this.speed as i64, 0, MAX_POWER)
+);
+motor_command!(
+    ForwardBackward,
+    [M1Drive, M2Drive],
+    None,
+    |this| this.speed,
+    validate | this | OutOfRange::check("speed", this.speed as i64, 0, MAX_POWER)
+);
+motor_command!(
+    Duty,
+    [M1DriveSignedDutyCycle, M2DriveSignedDutyCycle],
+    Some(Commands::MixDriveSignedDutyCycle),
+    |this| this.duty,
+    validate | this | OutOfRange::check("duty", this.duty as i64, -MAX_DUTY, MAX_DUTY)
+);
+motor_command!(
+    DutyAccel,
+    [M1DriveSignedDutyAccel, M2DriveSignedDutyAccel],
+    Some(Commands::MixDriveSignedDutyAccel),
+    |this| (this.duty, this.accel),
+    validate | this | {
+        OutOfRange::check("duty", this.duty as i64, -MAX_DUTY, MAX_DUTY)?;
+        OutOfRange::check("accel", this.accel as i64, 0, MAX_DUTY_ACCEL)
+    }
+);
+motor_command!(
+    Speed,
+    [M1DriveSignedSpeed, M2DriveSignedSpeed],
+    Some(Commands::MixDriveSignedSpeed),
+    |this| this.speed
+);
+motor_command!(
+    SpeedAccel,
+    [M1DriveSignedSpeedAccel, M2DriveSignedSpeedAccel],
+    Some(Commands::MixDriveIndividualSignedSpeedAccel),
+    |this| (this.accel, this.speed)
+);
+motor_command!(EncoderCount, [M1SetEncoder, M2SetEncoder], None, |this| {
+    this.count
+});
+motor_command!(
+    VelocityPid,
+    [M1SetVelocityPIDConst, M2SetVelocityPIDConst],
+    None,
+    |this| (this.d, this.p, this.i, this.qpps)
+);
+motor_command!(
+    PositionPid,
+    [M1SetPositionPIDConsts, M2SetPositionPIDConsts],
+    None,
+    |this| (
+        this.d,
+        this.p,
+        this.i,
+        this.max_i,
+        this.deadzone,
+        this.min,
+        this.max
+    ),
+    validate | this | OutOfRange::check("max", this.max as i64, this.min as i64, i32::MAX as i64)
+);
+motor_command!(
+    DefaultDutyAccel,
+    [M1SetDefaultDutyCycle, M2SetDefaultDutyCycle],
+    None,
+    |this| this.accel
+);
+motor_command!(
+    DefaultSpeed,
+    [M1SetDefaultSpeed, M2SetDefaultSpeed],
+    None,
+    |this| this.speed
+);
+motor_command!(
+    EncoderMode,
+    [M1SetEncoderMode, M2SetEncoderMode],
+    None,
+    |this| this.mode,
+    validate
+        | this
+        | OutOfRange::check(
+            "undefined mode bits",
+            (this.mode & !ENCODER_MODE_BITS) as i64,
+            0,
+            0
+        )
+);
+motor_command!(
+    AutoHome,
+    [M1SetAutoHomeDutySpeedTimeout, M2SetAutoHomeDutySpeedTimeout],
+    None,
+    |this| (this.percentage, this.timeout),
+    validate | this | OutOfRange::check("percentage", this.percentage as i64, 0, MAX_DUTY)
+);
+
+impl Encode for MaxCurrent {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        (self.max, self.min).encode(buffer)
+    }
+}
+
+impl MotorCommand for MaxCurrent {
+    const COMMANDS: [Commands; 2] = [Commands::M1SetMaxCurrent, Commands::M2SetMaxCurrent];
+
+    fn validate(&self) -> Result<(), OutOfRange> {
+        OutOfRange::check("min", self.min as i64, 0, self.max as i64)
+    }
+
+    fn validate_for(&self, capabilities: &Capabilities) -> Result<(), OutOfRange> {
+        match capabilities.max_current {
+            Some(max) => OutOfRange::check("max", self.max as i64, 0, max as i64),
+            None => Ok(()),
+        }
+    }
+}
+
+// The buffered commands end in a single buffer flag, so the arguments of both
+// motors cannot simply be concatenated.
+
+impl Encode for SpeedDistance {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        (self.speed, self.distance, self.execute_directly).encode(buffer)
+    }
+}
+
+impl MotorCommand for SpeedDistance {
+    const COMMANDS: [Commands; 2] = [
+        Commands::M1DriveSignedSpeedDistanceBuffered,
+        Commands::M2DriveSignedSpeedDistanceBuffered,
+    ];
+    const BOTH: Option<Commands> = Some(Commands::MixDriveSignedSpeedDistanceBuffered);
+
+    fn encode_both(m1: &Self, m2: &Self, buffer: &mut Vec<u8>) {
+        (
+            m1.speed,
+            m1.distance,
+            m2.speed,
+            m2.distance,
+            m1.execute_directly,
+        )
+            .encode(buffer)
+    }
+}
+
+impl Encode for SpeedAccelDistance {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        (self.accel, self.speed, self.distance, self.execute_directly).encode(buffer)
+    }
+}
+
+impl MotorCommand for SpeedAccelDistance {
+    const COMMANDS: [Commands; 2] = [
+        Commands::M1DriveSignedSpeedAccelDistanceBuffered,
+        Commands::M2DriveSignedSpeedAccelDistanceBuffered,
+    ];
+    const BOTH: Option<Commands> = Some(Commands::MixDriveIndividualSignedSpeedAccelDistance);
+
+    fn encode_both(m1: &Self, m2: &Self, buffer: &mut Vec<u8>) {
+        (
+            (m1.accel, m1.speed, m1.distance),
+            (m2.accel, m2.speed, m2.distance),
+            m1.execute_directly,
+        )
+            .encode(buffer)
+    }
+}
+
+impl Encode for SpeedAccelDecelPosition {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        (
+            self.accel,
+            self.speed,
+            self.decel,
+            self.position,
+            self.execute_directly,
+        )
+            .encode(buffer)
+    }
+}
+
+impl MotorCommand for SpeedAccelDecelPosition {
+    const COMMANDS: [Commands; 2] = [
+        Commands::M1DriveSpeedAccelDeccelPosition,
+        Commands::M2DriveSpeedAccelDeccelPosition,
+    ];
+    const BOTH: Option<Commands> = Some(Commands::MixDriveSpeedAccelDeccelPosition);
+
+    fn encode_both(m1: &Self, m2: &Self, buffer: &mut Vec<u8>) {
+        (
+            (m1.accel, m1.speed, m1.decel, m1.position),
+            (m2.accel, m2.speed, m2.decel, m2.position),
+            m1.execute_directly,
+        )
+            .encode(buffer)
+    }
+}
+
+impl Encode for SpeedPosition {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        (self.speed, self.position, self.execute_directly).encode(buffer)
+    }
+}
+
+impl MotorCommand for SpeedPosition {
+    const COMMANDS: [Commands; 2] = [
+        Commands::M1DriveSpeedPosition,
+        Commands::M2DriveSpeedPosition,
+    ];
+    const BOTH: Option<Commands> = Some(Commands::MixDriveSpeedPosition);
+
+    fn encode_both(m1: &Self, m2: &Self, buffer: &mut Vec<u8>) {
+        (
+            m1.speed,
+            m1.position,
+            m2.speed,
+            m2.position,
+            m1.execute_directly,
+        )
+            .encode(buffer)
+    }
+}
+
+impl Encode for Position {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        (self.position, self.execute_directly).encode(buffer)
+    }
+}
+
+impl MotorCommand for Position {
+    const COMMANDS: [Commands; 2] = [Commands::M1DrivePosition, Commands::M2DrivePosition];
+    const BOTH: Option<Commands> = Some(Commands::MixDrivePosition);
+
+    fn encode_both(m1: &Self, m2: &Self, buffer: &mut Vec<u8>) {
+        (m1.position, m2.position, m1.execute_directly).encode(buffer)
+    }
+}
+
+macro_rules! motor_reading {
+    ($ty:ident, shared $command:ident, $wire:ty, |$($field:pat),+| $value:expr) => {
+        motor_reading!(@decode $ty, $wire, |$($field),+| $value);
+
+        impl MotorReading for $ty {
+            const COMMANDS: [Commands; 2] = [Commands::$command; 2];
+            const SHARED: bool = true;
+        }
+    };
+    ($ty:ident, [$m1:ident, $m2:ident], $wire:ty, |$($field:pat),+| $value:expr) => {
+        motor_reading!(@decode $ty, $wire, |$($field),+| $value);
+
+        impl MotorReading for $ty {
+            const COMMANDS: [Commands; 2] = [Commands::$m1, Commands::$m2];
+        }
+    };
+    (@decode $ty:ident, $wire:ty, |$($field:pat),+| $value:expr) => {
+        impl Decode for $ty {
+            fn decode<R: WireRead + ?Sized>(reader: &mut R) -> Result<Self, ConnectionError> {
+                let ($($field,)+) = <$wire>::decode(reader)?;
+                Ok($value)
+            }
+
+            fn wire_size() -> Option<usize> {
+                <$wire>::wire_size()
+            }
+        }
+    };
+}
+
+motor_reading!(
+    Encoder,
+    [M1ReadEncoder, M2ReadEncoder],
+    (u32, u8),
+    |count, status| Encoder { count, status }
+);
+// The direction byte only repeats the sign of the speed.
+motor_reading!(
+    Speed,
+    [M1ReadSpeedCPS, M2ReadSpeedCPS],
+    (i32, u8),
+    |speed, _| Speed { speed }
+);
+motor_reading!(
+    RawSpeed,
+    [M1ReadRawSpeed, M2ReadRawSpeed],
+    (i32, u8),
+    |speed, _| RawSpeed { speed }
+);
+motor_reading!(
+    VelocityPid,
+    [M1ReadVelocityPIDConsts, M2ReadVelocityPIDConsts],
+    (u32, u32, u32, u32),
+    |p, i, d, qpps| VelocityPid { p, i, d, qpps }
+);
+motor_reading!(
+    PositionPid,
+    [M1ReadPositionPIDConsts, M2ReadPositionPIDConsts],
+    (u32, u32, u32, u32, u32, i32, i32),
+    |p, i, d, max_i, deadzone, min, max| PositionPid {
+        p,
+        i,
+        d,
+        max_i,
+        deadzone,
+        min,
+        max
+    }
+);
+motor_reading!(
+    MaxCurrent,
+    [M1ReadMaxCurrent, M2ReadMaxCurrent],
+    (u32, u32),
+    |max, min| MaxCurrent { max, min }
+);
+// The reads below answer for both motors in one response.
+motor_reading!(Pwm, shared ReadMotorPWMs, (i16,), |duty| Pwm { duty });
+motor_reading!(
+    AvgSpeed,
+    shared ReadMotorAvgSpeed,
+    (i32,),
+    |speed| AvgSpeed { speed }
+);
+motor_reading!(
+    DefaultSpeed,
+    shared ReadDefaultSpeedSettings,
+    (u16,),
+    |speed| DefaultSpeed { speed }
+);
+motor_reading!(
+    EncoderMode,
+    shared ReadEncoderModes,
+    (u8,),
+    |mode| EncoderMode { mode }
+);
+motor_reading!(
+    AutoHome,
+    shared ReadAutoHomeSettings,
+    (u16, u32),
+    |percentage, timeout| AutoHome { percentage, timeout }
+);
@@ -0,0 +1,162 @@
+//! This module describes how the serial port of a RoboClaw is opened.
+//!
+//! A `PortSpec` remembers either the device path or the USB identity of a
+//! RoboClaw, so that the port can be reopened after it disappeared, e.g. by
+//! `reconnect::ReconnectingTransport`. USB identities survive the device being
+//! enumerated under a different path after a reconnect or reboot.
+//!
+//! `list_roboclaws` lists the RoboClaws attached over USB with their identities.
+
+use serialport::{SerialPort, SerialPortType};
+use std::{fmt, time::Duration};
+
+/// USB vendor ID of the RoboClaw.
+pub const ROBOCLAW_USB_VID: u16 = 0x03EB;
+/// USB product ID of the RoboClaw.
+pub const ROBOCLAW_USB_PID: u16 = 0x2404;
+
+/// A RoboClaw attached over USB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortInfo {
+    pub path: String,                  // Current device path of the port
+    pub vid: u16,                      // USB vendor ID
+    pub pid: u16,                      // USB product ID
+    pub serial_number: Option<String>, // USB serial number, if the device reports one
+    pub manufacturer: Option<String>,  // Manufacturer string, if the device reports one
+    pub product: Option<String>,       // Product string, if the device reports one
+}
+
+impl PortInfo {
+    /// Returns a `PortSpec` finding this device by its USB identity, or by its path
+    /// if it reports no serial number.
+    pub fn spec(&self, baud_rate: u32) -> PortSpec {
+        match &self.serial_number {
+            Some(serial_number) => {
+                PortSpec::usb(self.vid, self.pid, Some(serial_number.clone()), baud_rate)
+            }
+            None => PortSpec::path(self.path.clone(), baud_rate),
+        }
+    }
+}
+
+/// Lists the serial ports of all RoboClaws attached over USB.
+pub fn list_roboclaws() -> serialport::Result<Vec<PortInfo>> {
+    Ok(serialport::available_ports()?
+        .into_iter()
+        .filter_map(|port| match port.port_type {
+            SerialPortType::UsbPort(info)
+                if info.vid == ROBOCLAW_USB_VID && info.pid == ROBOCLAW_USB_PID =>
+            {
+                Some(PortInfo {
+                    path: port.port_name,
+                    vid: info.vid,
+                    pid: info.pid,
+                    serial_number: info.serial_number,
+                    manufacturer: info.manufacturer,
+                    product: info.product,
+                })
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+/// How to find and open the serial port of a RoboClaw.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortSpec {
+    Path {
+        path: String,   // Device path, e.g. `/dev/ttyACM0` or `COM3`
+        baud_rate: u32, // Baud rate of the port
+    }, // Opens the port by device path.
+    Usb {
+        vid: u16,                      // USB vendor ID
+        pid: u16,                      // USB product ID
+        serial_number: Option<String>, // USB serial number, `None` accepts the first match
+        baud_rate: u32,                // Baud rate of the port
+    }, // Opens the first port matching the USB identity.
+}
+
+impl PortSpec {
+    /// Describes the port at the device `path`.
+    pub fn path<S: Into<String>>(path: S, baud_rate: u32) -> Self {
+        PortSpec::Path {
+            path: path.into(),
+            baud_rate,
+        }
+    }
+
+    /// Describes the port of the USB device with the given identity.
+    pub fn usb(vid: u16, pid: u16, serial_number: Option<String>, baud_rate: u32) -> Self {
+        PortSpec::Usb {
+            vid,
+            pid,
+            serial_number,
+            baud_rate,
+        }
+    }
+
+    /// Returns the baud rate of the port.
+    pub fn baud_rate(&self) -> u32 {
+        match self {
+            PortSpec::Path { baud_rate, .. } | PortSpec::Usb { baud_rate, .. } => *baud_rate,
+        }
+    }
+
+    /// Returns the device path the port can currently be opened at. For a USB
+    /// identity this enumerates the available ports and fails with
+    /// `ErrorKind::NoDevice` if none matches.
+    pub fn resolve(&self) -> serialport::Result<String> {
+        match self {
+            PortSpec::Path { path, .. } => Ok(path.clone()),
+            PortSpec::Usb {
+                vid,
+                pid,
+                serial_number,
+                ..
+            } => serialport::available_ports()?
+                .into_iter()
+                .find(|port| match &port.port_type {
+                    SerialPortType::UsbPort(info) => {
+                        info.vid == *vid
+                            && info.pid == *pid
+                            && (serial_number.is_none() || info.serial_number == *serial_number)
+                    }
+                    _ => false,
+                })
+                .map(|port| port.port_name)
+                .ok_or_else(|| {
+                    serialport::Error::new(
+                        serialport::ErrorKind::NoDevice,
+                        format!("no serial port matches {}", self),
+                    )
+                }),
+        }
+    }
+
+    /// Opens the port with the given read `timeout`.
+    pub fn open(&self, timeout: Duration) -> serialport::Result<Box<dyn SerialPort>> {
+        serialport::new(self.resolve()?, self.baud_rate())
+            .timeout(timeout)
+            .open()
+    }
+}
+
+impl fmt::Display for PortSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortSpec::Path { path, baud_rate } => write!(f, "{} at {} baud", path, baud_rate),
+            PortSpec::Usb {
+                vid,
+                pid,
+                serial_number,
+                baud_rate,
+            } => {
+                write!(f, "USB {:04x}:{:04x}", vid, pid)?;
+                if let Some(serial_number) = serial_number {
+                    write!(f, " serial {}", serial_number)?;
+                }
+                write!(f, " at {} baud", baud_rate)
+            }
+        }
+    }
+}
@@ -0,0 +1,403 @@
+//! This module provides a `Transport` that survives the serial port disappearing,
+//! as USB serial links do when a cable is jostled or hit by ESD.
+//!
+//! `ReconnectingTransport` remembers the `PortSpec` it was opened with. An I/O
+//! error other than a timeout is taken as a dead port: the port is dropped, a
+//! `ConnectionEvent::Disconnected` is reported, and every command fails with
+//! `ConnectionError::Disconnected` until the port could be reopened. Reopening is
+//! tried before each command, no more often than the reconnect backoff allows.
+//!
+//! Motion commands are never resent across a reconnect: a motion command whose
+//! attempt lost the port fails, and the caller decides whether the move is still
+//! wanted once `ConnectionEvent::Reconnected` was reported.
+
+use crate::{port::PortSpec, retry::Backoff, transport::Transport};
+use std::{
+    fmt, io,
+    time::{Duration, Instant},
+};
+
+/// A change of the state of a `ReconnectingTransport`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    Disconnected {
+        reason: String, // The error that revealed the dead port
+    }, // The port stopped working and was closed.
+    ReconnectAttempt {
+        attempt: u32, // Number of the attempt since the disconnect, counting from one
+    }, // Reopening the port is about to be tried.
+    Reconnected {
+        attempts: u32, // Number of attempts it took
+    }, // The port was reopened.
+}
+
+/// A callback receiving the `ConnectionEvent`s of a transport.
+pub(crate) type EventCallback = Box<dyn FnMut(&ConnectionEvent) + Send>;
+
+/// Opens the port of a `PortSpec` with a read timeout.
+type Opener = Box<dyn FnMut(&PortSpec, Duration) -> serialport::Result<Box<dyn Transport>> + Send>;
+
+/// A `Transport` reopening its serial port after the port died.
+pub struct ReconnectingTransport {
+    spec: PortSpec,                   // How to reopen the port
+    open: Opener,                     // Opens the port described by `spec`
+    port: Option<Box<dyn Transport>>, // The open port, `None` while disconnected
+    timeout: Duration,                // Read timeout, applied again after a reconnect
+    backoff: Backoff,                 // Delay between reconnect attempts
+    attempts: u32,                    // Reconnect attempts since the disconnect
+    next_attempt: Option<Instant>,    // Earliest time of the next reconnect attempt
+    on_event: Option<EventCallback>,  // Receives state changes
+}
+
+impl ReconnectingTransport {
+    /// Opens the port described by `spec`. Fails if the port cannot be opened now,
+    /// reconnecting only starts once the port has been open.
+    pub fn open(spec: PortSpec) -> serialport::Result<Self> {
+        ReconnectingTransport::with_opener(
+            spec,
+            Box::new(|spec, timeout| Ok(Box::new(spec.open(timeout)?))),
+        )
+    }
+
+    /// Opens the port described by `spec` with `open`, which is also used to
+    /// reopen it, e.g. to reconnect a fake port in tests.
+    fn with_opener(spec: PortSpec, mut open: Opener) -> serialport::Result<Self> {
+        let timeout = Duration::from_millis(5);
+        let port = open(&spec, timeout)?;
+        Ok(ReconnectingTransport {
+            spec,
+            open,
+            port: Some(port),
+            timeout,
+            backoff: Backoff::Exponential {
+                initial: Duration::from_millis(100),
+                max: Duration::from_secs(5),
+            },
+            attempts: 0,
+            next_attempt: None,
+            on_event: None,
+        })
+    }
+
+    /// Sets the delay between reconnect attempts. Defaults to an exponential
+    /// backoff from 100 ms up to 5 s.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets a callback receiving every `ConnectionEvent`. It is called from the
+    /// thread sending the command that noticed the change.
+    pub fn on_event<F: FnMut(&ConnectionEvent) + Send + 'static>(self, on_event: F) -> Self {
+        self.on_boxed_event(Box::new(on_event))
+    }
+
+    /// Sets the callback receiving every `ConnectionEvent`, see `on_event`.
+    pub(crate) fn on_boxed_event(mut self, on_event: EventCallback) -> Self {
+        self.on_event = Some(on_event);
+        self
+    }
+
+    /// Returns the description of the port.
+    pub fn spec(&self) -> &PortSpec {
+        &self.spec
+    }
+
+    fn emit(&mut self, event: ConnectionEvent) {
+        if let Some(on_event) = self.on_event.as_mut() {
+            on_event(&event);
+        }
+    }
+
+    /// Closes the dead port and schedules the first reconnect attempt.
+    fn disconnect(&mut self, reason: &dyn fmt::Display) {
+        if self.port.take().is_some() {
+            self.attempts = 0;
+            self.next_attempt = Some(Instant::now());
+            self.emit(ConnectionEvent::Disconnected {
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    /// Tries to reopen the port if it is closed and the backoff has passed.
+    fn reconnect(&mut self) {
+        if self.port.is_some() || self.next_attempt.is_some_and(|at| Instant::now() < at) {
+            return;
+        }
+        self.attempts += 1;
+        let attempt = self.attempts;
+        self.emit(ConnectionEvent::ReconnectAttempt { attempt });
+        match (self.open)(&self.spec, self.timeout) {
+            Ok(port) => {
+                self.port = Some(port);
+                self.next_attempt = None;
+                self.emit(ConnectionEvent::Reconnected { attempts: attempt });
+            }
+            Err(_) => {
+                self.next_attempt = Some(Instant::now() + self.backoff.delay(attempt as usize));
+            }
+        }
+    }
+
+    /// Returns the open port, or a `NotConnected` error while disconnected.
+    fn port(&mut self) -> io::Result<&mut Box<dyn Transport>> {
+        self.port
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "port disconnected"))
+    }
+
+    /// Passes the result of a read or write on. Any I/O error but a timeout or an
+    /// interruption closes the port and is reported as `NotConnected`.
+    fn check_io<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        match result {
+            Err(error) if !is_transient_io(error.kind()) => {
+                self.disconnect(&error);
+                Err(io::Error::new(io::ErrorKind::NotConnected, error))
+            }
+            result => result,
+        }
+    }
+
+    /// Passes the result of a port setting on. Any error but rejected input closes
+    /// the port and is reported as a missing device.
+    fn check_serial<T>(&mut self, result: serialport::Result<T>) -> serialport::Result<T> {
+        match result {
+            Err(error) if !matches!(error.kind(), serialport::ErrorKind::InvalidInput) => {
+                self.disconnect(&error);
+                Err(serialport::Error::new(
+                    serialport::ErrorKind::NoDevice,
+                    error.description,
+                ))
+            }
+            result => result,
+        }
+    }
+}
+
+impl Transport for ReconnectingTransport {
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let result = self.port()?.write_all(bytes);
+        self.check_io(result)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let result = self.port()?.read(buffer);
+        self.check_io(result)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        match self.port.as_mut() {
+            Some(port) => {
+                let result = port.set_timeout(timeout);
+                self.check_serial(result)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn clear(&mut self) -> serialport::Result<()> {
+        let result = self.port()?.clear();
+        self.check_serial(result)
+    }
+
+    fn bytes_to_read(&mut self) -> serialport::Result<u32> {
+        let result = self.port()?.bytes_to_read();
+        self.check_serial(result)
+    }
+
+    fn ensure_connected(&mut self) -> bool {
+        self.reconnect();
+        self.port.is_some()
+    }
+}
+
+/// Returns `true` for I/O errors that do not indicate a dead port.
+fn is_transient_io(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::TimedOut | io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        motor::{Duty, Motor},
+        Roboclaw,
+    };
+    use std::sync::{Arc, Mutex};
+
+    /// The state of the cable behind every `FakePort`.
+    #[derive(Default)]
+    struct Link {
+        alive: bool, // Whether the port works
+        refuse: u32, // Number of opens still refused
+        opens: u32,  // Number of opens tried
+        writes: u32, // Number of packets written
+        acks: usize, // Acks not yet read
+    }
+
+    /// A port answering every packet with an ack while its link is alive, and
+    /// failing with `NotConnected` otherwise.
+    struct FakePort(Arc<Mutex<Link>>);
+
+    impl FakePort {
+        fn link(&self) -> io::Result<std::sync::MutexGuard<'_, Link>> {
+            let link = self.0.lock().unwrap();
+            match link.alive {
+                true => Ok(link),
+                false => Err(io::Error::new(io::ErrorKind::NotConnected, "unplugged")),
+            }
+        }
+    }
+
+    impl Transport for FakePort {
+        fn write_all(&mut self, _bytes: &[u8]) -> io::Result<()> {
+            let mut link = self.link()?;
+            link.writes += 1;
+            link.acks += 1;
+            Ok(())
+        }
+
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let mut link = self.link()?;
+            if link.acks == 0 {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            link.acks -= 1;
+            buffer[0] = 0xFF;
+            Ok(1)
+        }
+
+        fn set_timeout(&mut self, _timeout: Duration) -> serialport::Result<()> {
+            Ok(())
+        }
+
+        fn clear(&mut self) -> serialport::Result<()> {
+            Ok(())
+        }
+
+        fn bytes_to_read(&mut self) -> serialport::Result<u32> {
+            Ok(0)
+        }
+    }
+
+    /// Returns an opener of `FakePort`s on `link`, refusing while `link.refuse`
+    /// is above zero.
+    fn opener(link: &Arc<Mutex<Link>>) -> Opener {
+        let link = link.clone();
+        Box::new(move |_, _| {
+            let mut state = link.lock().unwrap();
+            state.opens += 1;
+            if state.refuse > 0 {
+                state.refuse -= 1;
+                return Err(serialport::Error::new(
+                    serialport::ErrorKind::NoDevice,
+                    "no such device",
+                ));
+            }
+            state.alive = true;
+            Ok(Box::new(FakePort(link.clone())))
+        })
+    }
+
+    type Events = Arc<Mutex<Vec<ConnectionEvent>>>;
+
+    /// Opens a transport on a new link, recording its events.
+    fn fake_transport(backoff: Backoff) -> (ReconnectingTransport, Arc<Mutex<Link>>, Events) {
+        let link = Arc::new(Mutex::new(Link::default()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let transport =
+            ReconnectingTransport::with_opener(PortSpec::path("fake", 38_400), opener(&link))
+                .unwrap()
+                .backoff(backoff)
+                .on_event(move |event| recorded.lock().unwrap().push(event.clone()));
+        (transport, link, events)
+    }
+
+    fn unplug(link: &Mutex<Link>, refuse: u32) {
+        let mut link = link.lock().unwrap();
+        link.alive = false;
+        link.refuse = refuse;
+    }
+
+    #[test]
+    fn reopens_the_port_after_the_backoff() {
+        let (mut transport, link, events) =
+            fake_transport(Backoff::Fixed(Duration::from_secs(3600)));
+        transport.write_all(&[0x80]).unwrap();
+
+        unplug(&link, 1);
+        let error = transport.write_all(&[0x80]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotConnected);
+        assert_eq!(
+            transport.write_all(&[0x80]).unwrap_err().kind(),
+            io::ErrorKind::NotConnected
+        );
+
+        // The first attempt follows the disconnect right away and is refused, the
+        // next one waits for the backoff.
+        assert!(!transport.ensure_connected());
+        let refused_at = Instant::now();
+        assert!(!transport.ensure_connected());
+        assert_eq!(link.lock().unwrap().opens, 2);
+        assert!(transport.next_attempt.unwrap() > refused_at + Duration::from_secs(3599));
+
+        // Pretend the backoff has passed.
+        transport.next_attempt = Some(Instant::now());
+        assert!(transport.ensure_connected());
+        transport.write_all(&[0x80]).unwrap();
+        assert_eq!(link.lock().unwrap().opens, 3);
+
+        let events = events.lock().unwrap();
+        assert!(
+            matches!(&events[0], ConnectionEvent::Disconnected { reason } if reason == "unplugged")
+        );
+        assert_eq!(
+            events[1..],
+            [
+                ConnectionEvent::ReconnectAttempt { attempt: 1 },
+                ConnectionEvent::ReconnectAttempt { attempt: 2 },
+                ConnectionEvent::Reconnected { attempts: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn motion_commands_are_refused_until_reconnected() {
+        let (transport, link, events) = fake_transport(Backoff::None);
+        let mut roboclaw = Roboclaw::from_transport(transport, 0x80).unwrap();
+        let duty = Duty { duty: 1000 };
+        assert!(roboclaw.send(Motor::M1, duty).unwrap());
+
+        // The attempt that lost the port is not resent after reconnecting.
+        unplug(&link, 1);
+        assert!(roboclaw
+            .send(Motor::M1, duty)
+            .unwrap_err()
+            .is_disconnected());
+        assert_eq!(link.lock().unwrap().opens, 1);
+        // The reopen before the next command is refused, so is the command.
+        assert!(roboclaw
+            .send(Motor::M1, duty)
+            .unwrap_err()
+            .is_disconnected());
+        assert_eq!(link.lock().unwrap().opens, 2);
+        assert_eq!(link.lock().unwrap().writes, 1);
+
+        assert!(roboclaw.send(Motor::M1, duty).unwrap());
+        assert_eq!(link.lock().unwrap().writes, 2);
+        assert_eq!(
+            events.lock().unwrap()[1..],
+            [
+                ConnectionEvent::ReconnectAttempt { attempt: 1 },
+                ConnectionEvent::ReconnectAttempt { attempt: 2 },
+                ConnectionEvent::Reconnected { attempts: 2 },
+            ]
+        );
+    }
+}
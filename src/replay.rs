@@ -0,0 +1,402 @@
+//! This module replays a recorded session with a RoboClaw, so that field bugs can be
+//! turned into regression tests that run without hardware.
+//!
+//! A session is recorded by tracing a `Roboclaw` into a file:
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let port = serialport::new("/dev/ttyACM0", 38400).open()?;
+//! let mut roboclaw = roboclaw::Roboclaw::new(port, 0x80, None)?;
+//! roboclaw.set_trace(roboclaw::trace::TraceWriter::create("session.trace")?);
+//! roboclaw.read_encoders()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! and replayed with a `ReplayTransport`:
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use roboclaw::{replay::ReplayTransport, Roboclaw};
+//!
+//! let transport = ReplayTransport::open("session.trace")?;
+//! let handle = transport.handle();
+//! let mut roboclaw = Roboclaw::from_transport(transport, 0x80)?;
+//! roboclaw.read_encoders()?;
+//! handle.assert_finished();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every request must match the next recorded request byte for byte, it is then
+//! answered with the bytes received after it in the recording. A request that
+//! differs fails with an I/O error and is reported by `ReplayHandle::finish`.
+//! Timing is not replayed, a response that never arrived in the recording times out
+//! immediately.
+
+use crate::{
+    trace::{Direction, Frame},
+    transport::Transport,
+};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, ErrorKind},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// A deviation of the replayed session from the recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    Mismatch {
+        request: usize,    // Index of the request, counting from zero
+        expected: Vec<u8>, // The recorded request
+        actual: Vec<u8>,   // The request that was sent
+    }, // Represents a request that differs from the recording.
+    Exhausted {
+        actual: Vec<u8>, // The request that was sent
+    }, // Represents a request sent after the recording ended.
+    Unfinished {
+        remaining: usize, // Number of recorded requests that were not sent
+    }, // Represents a replay that ended before the recording.
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Mismatch {
+                request,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "request #{} differs from the recording, expected {:02x?} but sent {:02x?}",
+                request, expected, actual
+            ),
+            ReplayError::Exhausted { actual } => write!(
+                f,
+                "request {:02x?} was sent after the recording ended",
+                actual
+            ),
+            ReplayError::Unfinished { remaining } => {
+                write!(f, "{} recorded request(s) were never sent", remaining)
+            }
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+/// The replay state shared between the transport and its handles.
+#[derive(Debug)]
+struct ReplayState {
+    frames: VecDeque<Frame>,    // Recorded frames not replayed yet
+    pending: VecDeque<u8>,      // Response bytes of the current request not read yet
+    requests: usize,            // Number of requests replayed so far
+    error: Option<ReplayError>, // First deviation from the recording, if any
+}
+
+/// A `Transport` answering requests from a recorded trace.
+#[derive(Debug)]
+pub struct ReplayTransport {
+    state: Arc<Mutex<ReplayState>>,
+}
+
+impl ReplayTransport {
+    /// Loads the trace file at `path`, as written by `trace::TraceWriter`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        ReplayTransport::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Loads a trace in the trace file format from `reader`. Empty lines and lines
+    /// starting with `#` are skipped.
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut frames = Vec::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            match Frame::from_line(trimmed) {
+                Some(frame) => frames.push(frame),
+                None => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("line {} is not a valid frame: {}", number + 1, line),
+                    ))
+                }
+            }
+        }
+        Ok(ReplayTransport::from_frames(frames))
+    }
+
+    /// Creates a replay of `frames`, in the order they were exchanged.
+    pub fn from_frames<I: IntoIterator<Item = Frame>>(frames: I) -> Self {
+        ReplayTransport {
+            state: Arc::new(Mutex::new(ReplayState {
+                frames: frames.into_iter().collect(),
+                pending: VecDeque::new(),
+                requests: 0,
+                error: None,
+            })),
+        }
+    }
+
+    /// Returns a handle to check the outcome of the replay after the transport has
+    /// been handed to a `Roboclaw`.
+    pub fn handle(&self) -> ReplayHandle {
+        ReplayHandle {
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, ReplayState> {
+        self.state.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+impl Transport for ReplayTransport {
+    /// Checks `bytes` against the next recorded request and queues the recorded
+    /// response. A differing request is not consumed, so retries compare against the
+    /// same recorded request.
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let mut state = self.state();
+        let request = state.requests;
+        state.requests += 1;
+
+        let error = match state.frames.front() {
+            Some(frame) if frame.direction == Direction::Tx && frame.bytes == bytes => {
+                state.frames.pop_front();
+                while let Some(frame) = state.frames.front() {
+                    if frame.direction == Direction::Tx {
+                        break;
+                    }
+                    if let Some(frame) = state.frames.pop_front() {
+                        state.pending.extend(frame.bytes);
+                    }
+                }
+                return Ok(());
+            }
+            Some(frame) => ReplayError::Mismatch {
+                request,
+                expected: frame.bytes.clone(),
+                actual: bytes.to_vec(),
+            },
+            None => ReplayError::Exhausted {
+                actual: bytes.to_vec(),
+            },
+        };
+        let message = error.to_string();
+        state.error.get_or_insert(error);
+        Err(io::Error::new(ErrorKind::InvalidData, message))
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state();
+        if state.pending.is_empty() {
+            return Err(io::Error::new(ErrorKind::TimedOut, "no recorded response"));
+        }
+        let count = buffer.len().min(state.pending.len());
+        for (slot, byte) in buffer.iter_mut().zip(state.pending.drain(..count)) {
+            *slot = byte;
+        }
+        Ok(count)
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear(&mut self) -> serialport::Result<()> {
+        self.state().pending.clear();
+        Ok(())
+    }
+
+    fn bytes_to_read(&mut self) -> serialport::Result<u32> {
+        Ok(self.state().pending.len() as u32)
+    }
+}
+
+/// Checks the outcome of a replay.
+#[derive(Debug, Clone)]
+pub struct ReplayHandle {
+    state: Arc<Mutex<ReplayState>>,
+}
+
+impl ReplayHandle {
+    /// Returns the first deviation from the recording, or `Unfinished` if recorded
+    /// requests were never sent.
+    pub fn finish(&self) -> Result<(), ReplayError> {
+        let state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        if let Some(error) = &state.error {
+            return Err(error.clone());
+        }
+        let remaining = state
+            .frames
+            .iter()
+            .filter(|frame| frame.direction == Direction::Tx)
+            .count();
+        if remaining > 0 {
+            return Err(ReplayError::Unfinished { remaining });
+        }
+        Ok(())
+    }
+
+    /// Panics with the deviation reported by `finish`, if any.
+    pub fn assert_finished(&self) {
+        if let Err(error) = self.finish() {
+            panic!("replay failed: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{Commands, Roboclaw};
+    use crc16::{State, XMODEM};
+    use std::time::SystemTime;
+
+    /// Address of the controller in the recorded frames.
+    pub(crate) const ADDRESS: u8 = 0x80;
+
+    fn frame(direction: Direction, command: Commands, bytes: Vec<u8>) -> Frame {
+        Frame {
+            timestamp: SystemTime::UNIX_EPOCH,
+            direction,
+            address: ADDRESS,
+            command,
+            bytes,
+        }
+    }
+
+    /// Returns the recorded packet of a write command with encoded `args`.
+    pub(crate) fn write(command: Commands, args: &[u8]) -> Frame {
+        let mut bytes = vec![ADDRESS, command as u8];
+        bytes.extend_from_slice(args);
+        let crc = State::<XMODEM>::calculate(&bytes);
+        bytes.extend_from_slice(&crc.to_be_bytes());
+        frame(Direction::Tx, command, bytes)
+    }
+
+    /// Returns the recorded acknowledgement of a write command.
+    pub(crate) fn ack(command: Commands) -> Frame {
+        frame(Direction::Rx, command, vec![0xFF])
+    }
+
+    /// Returns the recorded request and response of a read command without
+    /// arguments answered with `data`.
+    pub(crate) fn read(command: Commands, data: &[u8]) -> [Frame; 2] {
+        let mut response = data.to_vec();
+        let mut covered = vec![ADDRESS, command as u8];
+        covered.extend_from_slice(data);
+        response.extend_from_slice(&State::<XMODEM>::calculate(&covered).to_be_bytes());
+        [
+            frame(Direction::Tx, command, vec![ADDRESS, command as u8]),
+            frame(Direction::Rx, command, response),
+        ]
+    }
+
+    /// A session with a controller: a battery read and an encoder reset.
+    fn session() -> Vec<Frame> {
+        let mut frames = read(Commands::ReadMainBatVoltage, &[0x00, 0xF0]).to_vec();
+        frames.push(write(Commands::ResetEncoders, &[]));
+        frames.push(ack(Commands::ResetEncoders));
+        frames
+    }
+
+    fn connect(frames: Vec<Frame>) -> (Roboclaw, ReplayHandle) {
+        let transport = ReplayTransport::from_frames(frames);
+        let handle = transport.handle();
+        (
+            Roboclaw::from_transport(transport, ADDRESS).unwrap(),
+            handle,
+        )
+    }
+
+    #[test]
+    fn recorded_session_replays() {
+        // Record the session through a trace sink, in the trace file format.
+        let (mut roboclaw, handle) = connect(session());
+        let trace = Arc::new(Mutex::new(String::new()));
+        {
+            let trace = Arc::clone(&trace);
+            roboclaw.set_trace(move |frame: &Frame| {
+                let mut trace = trace.lock().unwrap();
+                trace.push_str(&frame.to_line());
+                trace.push('\n');
+            });
+        }
+        assert_eq!(roboclaw.read_main_battery_voltage().unwrap(), 240);
+        assert!(roboclaw.reset_encoders().unwrap());
+        handle.assert_finished();
+
+        let trace = trace.lock().unwrap().clone();
+        assert_eq!(trace.lines().count(), 4);
+        let transport = ReplayTransport::from_reader(trace.as_bytes()).unwrap();
+        let handle = transport.handle();
+        let mut roboclaw = Roboclaw::from_transport(transport, ADDRESS).unwrap();
+        assert_eq!(roboclaw.read_main_battery_voltage().unwrap(), 240);
+        assert!(roboclaw.reset_encoders().unwrap());
+        handle.assert_finished();
+    }
+
+    #[test]
+    fn mismatched_request_fails() {
+        let (mut roboclaw, handle) = connect(session());
+        assert!(roboclaw.reset_encoders().is_err());
+        match handle.finish() {
+            Err(ReplayError::Mismatch {
+                request: 0,
+                expected,
+                actual,
+            }) => {
+                assert_eq!(expected, [ADDRESS, Commands::ReadMainBatVoltage as u8]);
+                assert_eq!(&actual[..2], [ADDRESS, Commands::ResetEncoders as u8]);
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unfinished_and_exhausted_replays_fail() {
+        let (mut roboclaw, handle) = connect(session());
+        roboclaw.read_main_battery_voltage().unwrap();
+        assert_eq!(
+            handle.finish(),
+            Err(ReplayError::Unfinished { remaining: 1 })
+        );
+
+        roboclaw.reset_encoders().unwrap();
+        handle.assert_finished();
+        assert!(roboclaw.reset_encoders().is_err());
+        assert!(matches!(
+            handle.finish(),
+            Err(ReplayError::Exhausted { .. })
+        ));
+    }
+
+    #[test]
+    fn missing_response_times_out() {
+        // The retry resends the request, which the recording does not have.
+        let (mut roboclaw, handle) = connect(vec![write(Commands::ResetEncoders, &[])]);
+        assert!(roboclaw.reset_encoders().is_err());
+        assert!(matches!(
+            handle.finish(),
+            Err(ReplayError::Exhausted { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_trace_lines_are_reported() {
+        let error =
+            ReplayTransport::from_reader("# comment\n\nnot a frame\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 3 "), "{}", error);
+    }
+}
@@ -0,0 +1,184 @@
+//! This module defines the `RetryPolicy` used by the connection to decide how often
+//! and how fast a failed command is retried.
+//!
+//! A policy is set per `Roboclaw` with `Roboclaw::set_retry_policy` and can be
+//! overridden for single calls with `Roboclaw::with_retry_policy`. Between attempts
+//! the connection waits for the backoff delay and then drains any stray bytes still
+//! arriving from the RoboClaw, so the next attempt starts in sync.
+
+use crate::errors::ConnectionError;
+use std::time::Duration;
+
+/// The delay between two attempts of the same command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    None,            // Retry immediately
+    Fixed(Duration), // Wait the same delay before every retry
+    Exponential {
+        initial: Duration, // Delay before the first retry
+        max: Duration,     // Upper bound for the delay
+    }, // Double the delay with every retry, up to `max`
+}
+
+impl Backoff {
+    /// Returns the delay before the retry following `failures` failed attempts.
+    pub fn delay(&self, failures: usize) -> Duration {
+        match *self {
+            Backoff::None => Duration::ZERO,
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                let shift = failures.saturating_sub(1).min(31) as u32;
+                initial.saturating_mul(1 << shift).min(max)
+            }
+        }
+    }
+}
+
+/// Describes how a failed command is retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u8,          // Total number of attempts, including the first one
+    timeout: Option<Duration>, // Per-attempt read timeout, `None` keeps the connection's timeouts
+    backoff: Backoff,          // Delay between attempts
+    retryable: fn(&ConnectionError) -> bool, // Decides which errors are worth another attempt
+}
+
+impl RetryPolicy {
+    /// Creates the default policy: three attempts without backoff, retrying protocol
+    /// and I/O errors.
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            timeout: None,
+            backoff: Backoff::None,
+            retryable: is_transient,
+        }
+    }
+
+    /// Creates a policy that makes a single attempt and never retries.
+    pub fn no_retry() -> Self {
+        RetryPolicy::new().max_attempts(1)
+    }
+
+    /// Sets the total number of attempts. Values below one are treated as one.
+    pub fn max_attempts(mut self, max_attempts: u8) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the read timeout of each attempt, overriding the connection's timeouts
+    /// for every command class.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the delay between attempts.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the predicate deciding which errors are retried.
+    pub fn retry_if(mut self, retryable: fn(&ConnectionError) -> bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    /// Returns the total number of attempts.
+    pub fn get_max_attempts(&self) -> u8 {
+        self.max_attempts
+    }
+
+    /// Returns the per-attempt timeout, if one is set.
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Returns the delay between attempts.
+    pub fn get_backoff(&self) -> Backoff {
+        self.backoff
+    }
+
+    /// Returns `true` if another attempt should follow after `error`, given that
+    /// `failures` attempts have failed so far. An `Unverified` delivery is never
+    /// retried, as the command may already be queued. The connection additionally
+    /// never retries motion commands after a disconnect.
+    pub fn should_retry(&self, error: &ConnectionError, failures: usize) -> bool {
+        failures < self.max_attempts as usize
+            && !matches!(error, ConnectionError::Unverified)
+            && (self.retryable)(error)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new()
+    }
+}
+
+/// The default retry predicate: protocol errors, I/O errors and disconnects are
+/// retried, other errors of the serial port itself are not. A retry after a
+/// disconnect only succeeds with a transport that reconnects.
+pub fn is_transient(error: &ConnectionError) -> bool {
+    error.is_protocol_error()
+        || matches!(
+            error,
+            ConnectionError::Io(_) | ConnectionError::Disconnected
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_delays() {
+        assert_eq!(Backoff::None.delay(3), Duration::ZERO);
+        let fixed = Backoff::Fixed(Duration::from_millis(20));
+        assert_eq!(fixed.delay(1), Duration::from_millis(20));
+        assert_eq!(fixed.delay(5), Duration::from_millis(20));
+
+        let exponential = Backoff::Exponential {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(50),
+        };
+        let delays: Vec<_> = (1..=5)
+            .map(|failures| exponential.delay(failures))
+            .collect();
+        assert_eq!(
+            delays,
+            [10, 20, 40, 50, 50].map(Duration::from_millis).to_vec()
+        );
+        // The shift is capped, so many failures cannot overflow.
+        assert_eq!(exponential.delay(usize::MAX), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn transient_errors_are_retried_until_the_attempts_run_out() {
+        let policy = RetryPolicy::new();
+        assert!(policy.should_retry(&ConnectionError::NoResponse, 1));
+        assert!(policy.should_retry(&ConnectionError::BadAck(0x00), 2));
+        assert!(policy.should_retry(&ConnectionError::Disconnected, 1));
+        assert!(!policy.should_retry(&ConnectionError::NoResponse, 3));
+        assert!(!RetryPolicy::no_retry().should_retry(&ConnectionError::NoResponse, 1));
+    }
+
+    #[test]
+    fn unverified_and_serial_errors_are_not_retried() {
+        let policy = RetryPolicy::new().retry_if(|_| true);
+        assert!(!policy.should_retry(&ConnectionError::Unverified, 1));
+
+        let serial = serialport::Error::new(serialport::ErrorKind::NoDevice, "gone");
+        assert!(!is_transient(&ConnectionError::Serial(serial)));
+        assert!(!RetryPolicy::new()
+            .retry_if(|error| matches!(error, ConnectionError::NoResponse))
+            .should_retry(&ConnectionError::BadAck(0x00), 1));
+    }
+
+    #[test]
+    fn attempts_are_at_least_one() {
+        assert_eq!(RetryPolicy::new().max_attempts(0).get_max_attempts(), 1);
+        assert_eq!(RetryPolicy::default().get_max_attempts(), 3);
+    }
+}
@@ -0,0 +1,811 @@
+//! This module holds the declarative description of the RoboClaw packet protocol.
+//!
+//! Every `Commands` variant has a `CommandSchema` in `SCHEMA` describing the
+//! fields of its request, whether the RoboClaw acknowledges it with `0xFF` or
+//! replies with data (and the fields of that data), the minimum firmware that
+//! supports it, whether it can safely be resent and whether it moves the motors.
+//! The connection checks the wrappers against this table, and tools such as packet
+//! decoders can be built on it without repeating the protocol knowledge.
+
+use crate::{firmware::FirmwareVersion, Commands};
+
+/// The wire type of a single packet field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    U8,
+    U16,
+    U32,
+    I8,
+    I16,
+    I32,
+    Bool,
+    String, // NUL-terminated, at most `codec::MAX_STRING_LEN` bytes
+}
+
+impl FieldType {
+    /// Returns the number of bytes the field takes on the wire, or `None` for strings.
+    pub const fn size(self) -> Option<usize> {
+        match self {
+            FieldType::U8 | FieldType::I8 | FieldType::Bool => Some(1),
+            FieldType::U16 | FieldType::I16 => Some(2),
+            FieldType::U32 | FieldType::I32 => Some(4),
+            FieldType::String => None,
+        }
+    }
+}
+
+/// A named field of a request or reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str, // Name of the field, as used in the RoboClaw manual
+    pub ty: FieldType,      // Wire type of the field
+}
+
+/// How the RoboClaw answers a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    Ack,                    // A single 0xFF byte
+    Data(&'static [Field]), // The listed fields followed by a CRC16
+}
+
+impl Reply {
+    /// Returns the fields of a data reply, or an empty slice for an acknowledgement.
+    pub fn fields(&self) -> &'static [Field] {
+        match self {
+            Reply::Ack => &[],
+            Reply::Data(fields) => fields,
+        }
+    }
+}
+
+/// The kind of exchange a command makes, used to pick its timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommandClass {
+    Read,   // Answered with data
+    Write,  // Acknowledged with 0xFF
+    Eeprom, // Reading or writing the EEPROM, acknowledged after the access completed
+}
+
+/// The description of a single command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandSchema {
+    pub command: Commands,                     // The described command
+    pub request: &'static [Field],             // Fields sent after the address and command byte
+    pub reply: Reply,                          // What the RoboClaw answers with
+    pub legacy_reply: Option<Reply>,           // Reply of older firmware, where it differs
+    pub min_firmware: Option<FirmwareVersion>, // Minimum firmware version supporting the command
+    pub idempotent: bool,                      // Whether resending the command is harmless
+    pub motion: bool,                          // Whether the command makes the motors move
+    pub eeprom: bool,                          // Whether the command accesses the EEPROM
+}
+
+impl CommandSchema {
+    /// Describes a command that is acknowledged with `0xFF`.
+    const fn write(command: Commands, request: &'static [Field]) -> Self {
+        CommandSchema {
+            command,
+            request,
+            reply: Reply::Ack,
+            legacy_reply: None,
+            min_firmware: None,
+            idempotent: true,
+            motion: false,
+            eeprom: false,
+        }
+    }
+
+    /// Describes a command that is answered with data.
+    const fn read(command: Commands, request: &'static [Field], reply: &'static [Field]) -> Self {
+        CommandSchema {
+            command,
+            request,
+            reply: Reply::Data(reply),
+            legacy_reply: None,
+            min_firmware: None,
+            idempotent: true,
+            motion: false,
+            eeprom: false,
+        }
+    }
+
+    /// Marks the command as only supported from `version` on. Unused until a newer
+    /// firmware command is verified, see the `commands` module.
+    #[allow(dead_code)]
+    const fn since(mut self, version: FirmwareVersion) -> Self {
+        self.min_firmware = Some(version);
+        self
+    }
+
+    /// Marks the command as queued in the motor buffers, so it must not be resent
+    /// blindly.
+    const fn queued(mut self) -> Self {
+        self.idempotent = false;
+        self
+    }
+
+    /// Marks the command as moving the motors. Motion commands are not resent after
+    /// the connection was lost.
+    const fn motion(mut self) -> Self {
+        self.motion = true;
+        self
+    }
+
+    /// Marks the command as reading or writing the EEPROM, which takes the RoboClaw
+    /// noticeably longer to acknowledge.
+    const fn eeprom(mut self) -> Self {
+        self.eeprom = true;
+        self
+    }
+
+    /// Sets the reply of firmware older than `EXTENDED_FIRMWARE`.
+    const fn legacy(mut self, reply: &'static [Field]) -> Self {
+        self.legacy_reply = Some(Reply::Data(reply));
+        self
+    }
+
+    /// Returns the class of the command, which selects its timeout.
+    pub fn class(&self) -> CommandClass {
+        match self.reply {
+            _ if self.eeprom => CommandClass::Eeprom,
+            Reply::Ack => CommandClass::Write,
+            Reply::Data(_) => CommandClass::Read,
+        }
+    }
+
+    /// Returns the size of the request fields in bytes, or `None` if it contains a string.
+    pub fn request_size(&self) -> Option<usize> {
+        fields_size(self.request)
+    }
+
+    /// Returns the size of the reply fields in bytes, without the CRC. An
+    /// acknowledgement has size zero, a reply containing a string `None`.
+    pub fn reply_size(&self) -> Option<usize> {
+        fields_size(self.reply.fields())
+    }
+
+    /// Returns `true` if a reply of `size` bytes (without CRC) matches this command,
+    /// on current or legacy firmware.
+    pub fn accepts_reply_size(&self, size: Option<usize>) -> bool {
+        self.reply_size() == size
+            || self
+                .legacy_reply
+                .is_some_and(|reply| fields_size(reply.fields()) == size)
+    }
+}
+
+/// Sums the sizes of `fields`, or returns `None` if one of them is a string.
+fn fields_size(fields: &[Field]) -> Option<usize> {
+    fields.iter().map(|field| field.ty.size()).sum()
+}
+
+macro_rules! fields {
+    ($($name:ident: $ty:ident),* $(,)?) => {
+        &[$(Field { name: stringify!($name), ty: FieldType::$ty }),*]
+    };
+}
+
+/// The schema of every command, sorted by command number.
+pub static SCHEMA: &[CommandSchema] = &[
+    CommandSchema::write(Commands::M1Forward, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::M1Backward, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::SetMinMainVoltage, fields![voltage: U8]),
+    CommandSchema::write(Commands::SetMaxMainVoltage, fields![voltage: U8]),
+    CommandSchema::write(Commands::M2Forward, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::M2Backward, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::M1Drive, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::M2Drive, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::MixDriveForward, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::MixDriveBackward, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::MixTurnRight, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::MixTurnLeft, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::MixDrive, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::TurnLeftRight, fields![speed: U8]).motion(),
+    CommandSchema::write(Commands::SetSerialTimeout, fields![timeout: U8]),
+    CommandSchema::read(Commands::ReadSerialTimeout, fields![], fields![timeout: U8]),
+    CommandSchema::read(
+        Commands::M1ReadEncoder,
+        fields![],
+        fields![count: U32, status: U8],
+    ),
+    CommandSchema::read(
+        Commands::M2ReadEncoder,
+        fields![],
+        fields![count: U32, status: U8],
+    ),
+    CommandSchema::read(
+        Commands::M1ReadSpeedCPS,
+        fields![],
+        fields![speed: U32, direction: U8],
+    ),
+    CommandSchema::read(
+        Commands::M2ReadSpeedCPS,
+        fields![],
+        fields![speed: U32, direction: U8],
+    ),
+    CommandSchema::write(Commands::ResetEncoders, fields![]),
+    CommandSchema::read(
+        Commands::ReadFirmwareVersion,
+        fields![],
+        fields![version: String],
+    ),
+    CommandSchema::write(Commands::M1SetEncoder, fields![count: I32]),
+    CommandSchema::write(Commands::M2SetEncoder, fields![count: I32]),
+    CommandSchema::read(
+        Commands::ReadMainBatVoltage,
+        fields![],
+        fields![voltage: U16],
+    ),
+    CommandSchema::read(
+        Commands::ReadLogicBatVoltage,
+        fields![],
+        fields![voltage: U16],
+    ),
+    CommandSchema::write(Commands::SetMinLogicVoltage, fields![voltage: U8]),
+    CommandSchema::write(Commands::SetMaxLogicVoltage, fields![voltage: U8]),
+    CommandSchema::write(
+        Commands::M1SetVelocityPIDConst,
+        fields![d: U32, p: U32, i: U32, qpps: U32],
+    ),
+    CommandSchema::write(
+        Commands::M2SetVelocityPIDConst,
+        fields![d: U32, p: U32, i: U32, qpps: U32],
+    ),
+    CommandSchema::read(
+        Commands::M1ReadRawSpeed,
+        fields![],
+        fields![speed: U32, direction: U8],
+    ),
+    CommandSchema::read(
+        Commands::M2ReadRawSpeed,
+        fields![],
+        fields![speed: U32, direction: U8],
+    ),
+    CommandSchema::write(Commands::M1DriveSignedDutyCycle, fields![duty: I16]).motion(),
+    CommandSchema::write(Commands::M2DriveSignedDutyCycle, fields![duty: I16]).motion(),
+    CommandSchema::write(
+        Commands::MixDriveSignedDutyCycle,
+        fields![duty1: I16, duty2: I16],
+    )
+    .motion(),
+    CommandSchema::write(Commands::M1DriveSignedSpeed, fields![speed: I32]).motion(),
+    CommandSchema::write(Commands::M2DriveSignedSpeed, fields![speed: I32]).motion(),
+    CommandSchema::write(
+        Commands::MixDriveSignedSpeed,
+        fields![speed1: I32, speed2: I32],
+    )
+    .motion(),
+    CommandSchema::write(
+        Commands::M1DriveSignedSpeedAccel,
+        fields![accel: U32, speed: I32],
+    )
+    .motion(),
+    CommandSchema::write(
+        Commands::M2DriveSignedSpeedAccel,
+        fields![accel: U32, speed: I32],
+    )
+    .motion(),
+    CommandSchema::write(
+        Commands::MixDriveSignedSpeedAccel,
+        fields![accel: U32, speed1: I32, speed2: I32],
+    )
+    .motion(),
+    CommandSchema::write(
+        Commands::M1DriveSignedSpeedDistanceBuffered,
+        fields![speed: I32, distance: U32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::M2DriveSignedSpeedDistanceBuffered,
+        fields![speed: I32, distance: U32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::MixDriveSignedSpeedDistanceBuffered,
+        fields![speed1: I32, distance1: U32, speed2: I32, distance2: U32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::M1DriveSignedSpeedAccelDistanceBuffered,
+        fields![accel: U32, speed: I32, distance: U32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::M2DriveSignedSpeedAccelDistanceBuffered,
+        fields![accel: U32, speed: I32, distance: U32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::MixDriveSignedSpeedAccelDistanceBuffered,
+        fields![accel: U32, speed1: I32, distance1: U32, speed2: I32, distance2: U32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::read(
+        Commands::ReadBufferLength,
+        fields![],
+        fields![buffer1: U8, buffer2: U8],
+    ),
+    CommandSchema::read(
+        Commands::ReadMotorPWMs,
+        fields![],
+        fields![pwm1: I16, pwm2: I16],
+    ),
+    CommandSchema::read(
+        Commands::ReadMotorCurrents,
+        fields![],
+        fields![current1: I16, current2: I16],
+    ),
+    CommandSchema::write(
+        Commands::MixDriveIndividualSignedSpeedAccel,
+        fields![accel1: U32, speed1: I32, accel2: U32, speed2: I32],
+    )
+    .motion(),
+    CommandSchema::write(
+        Commands::MixDriveIndividualSignedSpeedAccelDistance,
+        fields![
+            accel1: U32,
+            speed1: I32,
+            distance1: U32,
+            accel2: U32,
+            speed2: I32,
+            distance2: U32,
+            buffer: U8,
+        ],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::M1DriveSignedDutyAccel,
+        fields![duty: I16, accel: U32],
+    )
+    .motion(),
+    CommandSchema::write(
+        Commands::M2DriveSignedDutyAccel,
+        fields![duty: I16, accel: U32],
+    )
+    .motion(),
+    CommandSchema::write(
+        Commands::MixDriveSignedDutyAccel,
+        fields![duty1: I16, accel1: U32, duty2: I16, accel2: U32],
+    )
+    .motion(),
+    CommandSchema::read(
+        Commands::M1ReadVelocityPIDConsts,
+        fields![],
+        fields![p: U32, i: U32, d: U32, qpps: U32],
+    ),
+    CommandSchema::read(
+        Commands::M2ReadVelocityPIDConsts,
+        fields![],
+        fields![p: U32, i: U32, d: U32, qpps: U32],
+    ),
+    CommandSchema::write(Commands::SetMainBatVoltage, fields![min: U16, max: U16]),
+    CommandSchema::write(Commands::SetLogicBatVoltage, fields![min: U16, max: U16]),
+    CommandSchema::read(
+        Commands::ReadMainBatVoltageSettings,
+        fields![],
+        fields![min: U16, max: U16],
+    ),
+    CommandSchema::read(
+        Commands::ReadLogicBatVoltageSettings,
+        fields![],
+        fields![min: U16, max: U16],
+    ),
+    CommandSchema::write(
+        Commands::M1SetPositionPIDConsts,
+        fields![d: U32, p: U32, i: U32, max_i: U32, deadzone: U32, min: I32, max: I32],
+    ),
+    CommandSchema::write(
+        Commands::M2SetPositionPIDConsts,
+        fields![d: U32, p: U32, i: U32, max_i: U32, deadzone: U32, min: I32, max: I32],
+    ),
+    CommandSchema::read(
+        Commands::M1ReadPositionPIDConsts,
+        fields![],
+        fields![p: U32, i: U32, d: U32, max_i: U32, deadzone: U32, min: I32, max: I32],
+    ),
+    CommandSchema::read(
+        Commands::M2ReadPositionPIDConsts,
+        fields![],
+        fields![p: U32, i: U32, d: U32, max_i: U32, deadzone: U32, min: I32, max: I32],
+    ),
+    CommandSchema::write(
+        Commands::M1DriveSpeedAccelDeccelPosition,
+        fields![accel: U32, speed: U32, deccel: U32, position: I32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::M2DriveSpeedAccelDeccelPosition,
+        fields![accel: U32, speed: U32, deccel: U32, position: I32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::MixDriveSpeedAccelDeccelPosition,
+        fields![
+            accel1: U32,
+            speed1: U32,
+            deccel1: U32,
+            position1: I32,
+            accel2: U32,
+            speed2: U32,
+            deccel2: U32,
+            position2: I32,
+            buffer: U8,
+        ],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(Commands::M1SetDefaultDutyCycle, fields![accel: U16]),
+    CommandSchema::write(Commands::M2SetDefaultDutyCycle, fields![accel: U16]),
+    CommandSchema::write(Commands::M1SetDefaultSpeed, fields![speed: U16]),
+    CommandSchema::write(Commands::M2SetDefaultSpeed, fields![speed: U16]),
+    CommandSchema::read(
+        Commands::ReadDefaultSpeedSettings,
+        fields![],
+        fields![speed1: U16, speed2: U16],
+    ),
+    CommandSchema::write(Commands::SetS3S4S5Modes, fields![s3: U8, s4: U8, s5: U8]),
+    CommandSchema::read(
+        Commands::ReadS3S4S5Modes,
+        fields![],
+        fields![s3: U8, s4: U8, s5: U8],
+    ),
+    CommandSchema::write(
+        Commands::SetDeadBandRCAnalog,
+        fields![reverse: U8, forward: U8],
+    ),
+    CommandSchema::read(
+        Commands::ReadDeadBandRCAnalog,
+        fields![],
+        fields![reverse: U8, forward: U8],
+    ),
+    CommandSchema::read(
+        Commands::ReadEncoderCounts,
+        fields![],
+        fields![count1: U32, count2: U32],
+    ),
+    CommandSchema::read(
+        Commands::ReadRawMotorSpeeds,
+        fields![],
+        fields![speed1: U32, speed2: U32],
+    ),
+    CommandSchema::write(Commands::RestoreDefaults, fields![]).eeprom(),
+    CommandSchema::read(
+        Commands::ReadDefaultDutyCycleAccel,
+        fields![],
+        fields![accel1: U32, accel2: U32],
+    ),
+    CommandSchema::read(
+        Commands::ReadTemperature1,
+        fields![],
+        fields![temperature: U16],
+    ),
+    CommandSchema::read(
+        Commands::ReadTemperature2,
+        fields![],
+        fields![temperature: U16],
+    ),
+    CommandSchema::read(Commands::ReadStatus, fields![], fields![status: U32])
+        .legacy(fields![status: U16]),
+    CommandSchema::read(
+        Commands::ReadEncoderModes,
+        fields![],
+        fields![mode1: U8, mode2: U8],
+    ),
+    CommandSchema::write(Commands::M1SetEncoderMode, fields![mode: U8]),
+    CommandSchema::write(Commands::M2SetEncoderMode, fields![mode: U8]),
+    CommandSchema::write(Commands::EEPROMWriteSettings, fields![]).eeprom(),
+    CommandSchema::write(Commands::EEPROMReadSettings, fields![]).eeprom(),
+    CommandSchema::write(Commands::SetStandardConfigSettings, fields![config: U16]),
+    CommandSchema::read(
+        Commands::ReadStandardConfigSettings,
+        fields![],
+        fields![config: U16],
+    ),
+    CommandSchema::write(Commands::SetCTRLModes, fields![mode1: U8, mode2: U8]),
+    CommandSchema::read(
+        Commands::ReadCTRLModes,
+        fields![],
+        fields![mode1: U8, mode2: U8],
+    ),
+    CommandSchema::write(Commands::SetCTRL1, fields![value: U16]),
+    CommandSchema::write(Commands::SetCTRL2, fields![value: U16]),
+    CommandSchema::read(
+        Commands::ReadCTRLs,
+        fields![],
+        fields![value1: U16, value2: U16],
+    ),
+    CommandSchema::write(
+        Commands::M1SetAutoHomeDutySpeedTimeout,
+        fields![percentage: U16, timeout: U32],
+    ),
+    CommandSchema::write(
+        Commands::M2SetAutoHomeDutySpeedTimeout,
+        fields![percentage: U16, timeout: U32],
+    ),
+    CommandSchema::read(
+        Commands::ReadAutoHomeSettings,
+        fields![],
+        fields![percentage1: U16, timeout1: U32, percentage2: U16, timeout2: U32],
+    ),
+    CommandSchema::read(
+        Commands::ReadMotorAvgSpeed,
+        fields![],
+        fields![speed1: I32, speed2: I32],
+    ),
+    CommandSchema::write(
+        Commands::SetSpeedErrorLimits,
+        fields![limit1: U32, limit2: U32],
+    ),
+    CommandSchema::read(
+        Commands::ReadSpeedErrorLimits,
+        fields![],
+        fields![limit1: U32, limit2: U32],
+    ),
+    CommandSchema::read(
+        Commands::ReadSpeedErrors,
+        fields![],
+        fields![error1: I32, error2: I32],
+    ),
+    CommandSchema::write(
+        Commands::SetPositionErrorLimits,
+        fields![limit1: U32, limit2: U32],
+    ),
+    CommandSchema::read(
+        Commands::ReadPositionErrorLimits,
+        fields![],
+        fields![limit1: U32, limit2: U32],
+    ),
+    CommandSchema::read(
+        Commands::ReadPositionErrors,
+        fields![],
+        fields![error1: I32, error2: I32],
+    ),
+    CommandSchema::write(
+        Commands::SetBatteryVoltageOffsets,
+        fields![main: I8, logic: I8],
+    ),
+    CommandSchema::read(
+        Commands::ReadBatteryVoltageOffsets,
+        fields![],
+        fields![main: I8, logic: I8],
+    ),
+    CommandSchema::write(
+        Commands::SetCurrentBlankingPercentages,
+        fields![blanking1: U16, blanking2: U16],
+    ),
+    CommandSchema::read(
+        Commands::ReadCurrentBlankinPercentages,
+        fields![],
+        fields![blanking1: U16, blanking2: U16],
+    ),
+    CommandSchema::write(
+        Commands::M1DrivePosition,
+        fields![position: I32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::M2DrivePosition,
+        fields![position: I32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::MixDrivePosition,
+        fields![position1: I32, position2: I32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::M1DriveSpeedPosition,
+        fields![speed: U32, position: I32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::M2DriveSpeedPosition,
+        fields![speed: U32, position: I32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(
+        Commands::MixDriveSpeedPosition,
+        fields![speed1: U32, position1: I32, speed2: U32, position2: I32, buffer: U8],
+    )
+    .queued()
+    .motion(),
+    CommandSchema::write(Commands::M1SetMaxCurrent, fields![max: U32, min: U32]),
+    CommandSchema::write(Commands::M2SetMaxCurrent, fields![max: U32, min: U32]),
+    CommandSchema::read(
+        Commands::M1ReadMaxCurrent,
+        fields![],
+        fields![max: U32, min: U32],
+    ),
+    CommandSchema::read(
+        Commands::M2ReadMaxCurrent,
+        fields![],
+        fields![max: U32, min: U32],
+    ),
+    CommandSchema::write(Commands::SetPWMMode, fields![mode: U8]),
+    CommandSchema::read(Commands::ReadPWMMode, fields![], fields![mode: U8]),
+    CommandSchema::read(
+        Commands::EEPROMReadUserMemoryLocation,
+        fields![location: U8],
+        fields![value: U16],
+    ),
+    CommandSchema::write(
+        Commands::EEPROMWriteUserMemoryLocation,
+        fields![location: U8, value: U16],
+    )
+    .eeprom(),
+];
+
+impl Commands {
+    /// Returns the schema describing this command.
+    pub fn schema(self) -> &'static CommandSchema {
+        let index = SCHEMA
+            .binary_search_by_key(&(self as u8), |schema| schema.command as u8)
+            .expect("every command has a schema");
+        &SCHEMA[index]
+    }
+
+    /// Returns the command with the number `value`, if there is one.
+    pub fn from_u8(value: u8) -> Option<Commands> {
+        SCHEMA
+            .binary_search_by_key(&value, |schema| schema.command as u8)
+            .ok()
+            .map(|index| SCHEMA[index].command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::Encode,
+        motor::{self, MotorCommand, MotorReading},
+    };
+
+    #[test]
+    fn schema_is_sorted_by_command_number() {
+        for pair in SCHEMA.windows(2) {
+            assert!(
+                (pair[0].command as u8) < (pair[1].command as u8),
+                "{:?} is not sorted before {:?}",
+                pair[0].command,
+                pair[1].command
+            );
+        }
+    }
+
+    #[test]
+    fn every_command_has_a_schema() {
+        // Read the variants from the source, as the enum cannot be iterated.
+        let variants: Vec<(&str, u8)> = include_str!("commands.rs")
+            .lines()
+            .filter_map(|line| {
+                let (name, number) = line.trim().strip_suffix(',')?.split_once(" = ")?;
+                Some((name, number.parse().ok()?))
+            })
+            .collect();
+        assert_eq!(variants.len(), SCHEMA.len());
+        for (name, number) in variants {
+            let command = Commands::from_u8(number)
+                .unwrap_or_else(|| panic!("{} ({}) has no schema", name, number));
+            assert_eq!(format!("{:?}", command), name);
+            assert_eq!(command.schema().command, command);
+        }
+    }
+
+    fn check_command<C: MotorCommand>(args: C) {
+        let mut buffer = Vec::new();
+        args.encode(&mut buffer);
+        for command in C::COMMANDS {
+            assert_eq!(
+                command.schema().request_size(),
+                Some(buffer.len()),
+                "{:?}",
+                command
+            );
+        }
+        if let Some(both) = C::BOTH {
+            let mut buffer = Vec::new();
+            motor::Both(&args, &args).encode(&mut buffer);
+            assert_eq!(
+                both.schema().request_size(),
+                Some(buffer.len()),
+                "{:?}",
+                both
+            );
+        }
+    }
+
+    fn check_reading<R: MotorReading>() {
+        for command in R::COMMANDS {
+            assert!(
+                command.schema().accepts_reply_size(R::wire_size()),
+                "{:?}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn motor_wrappers_agree_with_the_schema() {
+        use motor::*;
+
+        check_command(Forward { speed: 0 });
+        check_command(Backward { speed: 0 });
+        check_command(ForwardBackward { speed: 64 });
+        check_command(Duty { duty: 0 });
+        check_command(DutyAccel { duty: 0, accel: 0 });
+        check_command(Speed { speed: 0 });
+        check_command(SpeedAccel { accel: 0, speed: 0 });
+        check_command(SpeedDistance {
+            speed: 0,
+            distance: 0,
+            execute_directly: false,
+        });
+        check_command(SpeedAccelDistance {
+            accel: 0,
+            speed: 0,
+            distance: 0,
+            execute_directly: false,
+        });
+        check_command(SpeedAccelDecelPosition {
+            accel: 0,
+            speed: 0,
+            decel: 0,
+            position: 0,
+            execute_directly: false,
+        });
+        check_command(SpeedPosition {
+            speed: 0,
+            position: 0,
+            execute_directly: false,
+        });
+        check_command(Position {
+            position: 0,
+            execute_directly: false,
+        });
+        check_command(EncoderCount { count: 0 });
+        check_command(VelocityPid {
+            p: 0,
+            i: 0,
+            d: 0,
+            qpps: 0,
+        });
+        check_command(PositionPid {
+            p: 0,
+            i: 0,
+            d: 0,
+            max_i: 0,
+            deadzone: 0,
+            min: 0,
+            max: 0,
+        });
+        check_command(DefaultDutyAccel { accel: 0 });
+        check_command(DefaultSpeed { speed: 0 });
+        check_command(EncoderMode { mode: 0 });
+        check_command(AutoHome {
+            percentage: 0,
+            timeout: 0,
+        });
+        check_command(MaxCurrent { max: 0, min: 0 });
+
+        check_reading::<Encoder>();
+        check_reading::<Speed>();
+        check_reading::<RawSpeed>();
+        check_reading::<VelocityPid>();
+        check_reading::<PositionPid>();
+        check_reading::<MaxCurrent>();
+    }
+}
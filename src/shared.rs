@@ -0,0 +1,186 @@
+//! This module provides `SharedRoboclaw`, a cloneable handle to a `Roboclaw` that
+//! can be used from several threads at once, e.g. one reading telemetry and one
+//! sending drive commands.
+//!
+//! Commands from different threads are serialized on the connection. Commands sent
+//! with `SharedRoboclaw::with_priority`, such as `SharedRoboclaw::stop`, jump the
+//! queue: they only wait for the command already on the wire, never for commands
+//! queued behind it. The command on the wire also gives up its remaining retries as
+//! soon as a priority command is waiting, so a stop is not held up by a read timing
+//! out repeatedly.
+
+use crate::{
+    errors::RoboClawError,
+    heartbeat::Heartbeat,
+    motor::{Duty, Motor, Speed},
+    sync::lock,
+    Roboclaw,
+};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+/// The state shared between the clones of a `SharedRoboclaw`.
+struct Inner {
+    roboclaw: Mutex<Roboclaw>, // The controller, locked for the duration of a command
+    priority: Mutex<usize>,    // Number of priority commands waiting or running
+    cleared: Condvar,          // Signalled when the last priority command finished
+    waiting: Arc<AtomicUsize>, // Number of priority commands waiting for the lock
+}
+
+/// A cloneable, thread-safe handle to a `Roboclaw`, see the module documentation.
+#[derive(Clone)]
+pub struct SharedRoboclaw {
+    inner: Arc<Inner>,
+}
+
+impl SharedRoboclaw {
+    /// Wraps `roboclaw` for use from several threads.
+    pub fn new(mut roboclaw: Roboclaw) -> Self {
+        let waiting = Arc::new(AtomicUsize::new(0));
+        roboclaw.connection.preempt = Some(Arc::clone(&waiting));
+        SharedRoboclaw {
+            inner: Arc::new(Inner {
+                roboclaw: Mutex::new(roboclaw),
+                priority: Mutex::new(0),
+                cleared: Condvar::new(),
+                waiting,
+            }),
+        }
+    }
+
+    /// Runs `f` with exclusive access to the `Roboclaw`, after any waiting priority
+    /// commands.
+    pub fn with<T>(&self, f: impl FnOnce(&mut Roboclaw) -> T) -> T {
+        loop {
+            let mut priority = lock(&self.inner.priority);
+            while *priority > 0 {
+                priority = self
+                    .inner
+                    .cleared
+                    .wait(priority)
+                    .unwrap_or_else(|error| error.into_inner());
+            }
+            drop(priority);
+
+            let mut roboclaw = lock(&self.inner.roboclaw);
+            // The mutex is not fair: a priority command that arrived while this one
+            // waited for the lock must not lose to it, so back off and let it go first.
+            if *lock(&self.inner.priority) == 0 {
+                return f(&mut roboclaw);
+            }
+        }
+    }
+
+    /// Runs `f` with exclusive access to the `Roboclaw` ahead of all commands that
+    /// are not on the wire yet. Meant for safety-critical commands only.
+    pub fn with_priority<T>(&self, f: impl FnOnce(&mut Roboclaw) -> T) -> T {
+        *lock(&self.inner.priority) += 1;
+        let _priority = PriorityGuard(&self.inner);
+        self.inner.waiting.fetch_add(1, Ordering::AcqRel);
+        let mut roboclaw = lock(&self.inner.roboclaw);
+        self.inner.waiting.fetch_sub(1, Ordering::AcqRel);
+        f(&mut roboclaw)
+    }
+
+    /// Cuts the power to both motors with a zero duty cycle, as a priority command.
+    /// The motors coast to a stop.
+    pub fn stop(&self) -> Result<bool, RoboClawError> {
+        self.with_priority(|roboclaw| roboclaw.send(Motor::Both, Duty { duty: 0 }))
+    }
+
+    /// Commands zero speed on both motors, as a priority command. The speed PID
+    /// actively brakes the motors, which requires encoders.
+    pub fn zero_speed(&self) -> Result<bool, RoboClawError> {
+        self.with_priority(|roboclaw| roboclaw.send(Motor::Both, Speed { speed: 0 }))
+    }
+
+    /// Starts a background `Heartbeat` on this handle, see
+    /// `Roboclaw::spawn_heartbeat`. Heartbeats wait behind priority commands like
+    /// any other command.
+    pub fn spawn_heartbeat<F>(&self, interval: Duration, healthy: F) -> Heartbeat
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        let shared = self.clone();
+        Heartbeat::spawn(interval, healthy, move || {
+            shared.with(Roboclaw::beat);
+            true
+        })
+    }
+}
+
+impl From<Roboclaw> for SharedRoboclaw {
+    fn from(roboclaw: Roboclaw) -> Self {
+        SharedRoboclaw::new(roboclaw)
+    }
+}
+
+/// Ends a priority command when dropped, even if its closure panicked.
+struct PriorityGuard<'a>(&'a Inner);
+
+impl Drop for PriorityGuard<'_> {
+    fn drop(&mut self) {
+        let mut priority = lock(&self.0.priority);
+        *priority -= 1;
+        if *priority == 0 {
+            self.0.cleared.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayTransport;
+    use std::{sync::mpsc, thread};
+
+    #[test]
+    fn priority_command_overtakes_a_blocked_command() {
+        let transport = ReplayTransport::from_frames(Vec::new());
+        let shared = SharedRoboclaw::from(Roboclaw::from_transport(transport, 0x80).unwrap());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let (release, released) = mpsc::channel::<()>();
+        let (started, busy) = mpsc::channel();
+
+        let holder = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                shared.with(|_| {
+                    started.send(()).unwrap();
+                    released.recv().unwrap();
+                })
+            })
+        };
+        busy.recv().unwrap();
+
+        let spawn = |priority: bool| {
+            let (shared, order) = (shared.clone(), Arc::clone(&order));
+            thread::spawn(move || {
+                let record = |_: &mut Roboclaw| lock(&order).push(priority);
+                if priority {
+                    shared.with_priority(record)
+                } else {
+                    shared.with(record)
+                }
+            })
+        };
+        // Wherever the normal command is when the lock is released, waiting for
+        // the priority check or already for the lock, the urgent one goes first.
+        let normal = spawn(false);
+        let urgent = spawn(true);
+        while shared.inner.waiting.load(Ordering::Acquire) == 0 {
+            thread::yield_now();
+        }
+
+        release.send(()).unwrap();
+        for thread in [holder, normal, urgent] {
+            thread.join().unwrap();
+        }
+        assert_eq!(*lock(&order), vec![true, false]);
+    }
+}
@@ -0,0 +1,293 @@
+//! This module collects statistics about the commands exchanged with a RoboClaw.
+//!
+//! Every command sent through a `Roboclaw` is counted, together with its retries and
+//! the kind of error of every failed attempt, and the round-trip time of its
+//! successful attempt is added to a latency histogram. `Roboclaw::stats` returns the
+//! statistics per command and in total. A slowly rising rate of retries, timeouts or
+//! CRC errors usually points to a failing cable or connector.
+
+use crate::{errors::ConnectionError, Commands};
+use std::{collections::HashMap, time::Duration};
+
+/// Upper bounds of the latency histogram buckets, in microseconds. A last bucket
+/// collects everything slower.
+const BUCKET_BOUNDS_US: [u64; 10] = [
+    500, 1_000, 2_000, 5_000, 10_000, 20_000, 50_000, 100_000, 200_000, 500_000,
+];
+
+/// A histogram of round-trip latencies.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: [u64; BUCKET_BOUNDS_US.len() + 1], // Number of samples per bucket
+    total: Duration,                           // Sum of all samples
+    max: Duration,                             // Slowest sample
+}
+
+impl LatencyHistogram {
+    /// Adds a sample to the histogram.
+    pub fn record(&mut self, latency: Duration) {
+        let micros = latency.as_micros();
+        let bucket = BUCKET_BOUNDS_US
+            .iter()
+            .position(|&bound| micros <= bound as u128)
+            .unwrap_or(BUCKET_BOUNDS_US.len());
+        self.counts[bucket] += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    /// Returns the number of samples.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the mean latency, or `None` without samples.
+    pub fn mean(&self) -> Option<Duration> {
+        match self.count() {
+            0 => None,
+            count => Some(Duration::from_nanos(
+                (self.total.as_nanos() / count as u128) as u64,
+            )),
+        }
+    }
+
+    /// Returns the slowest sample, or `None` without samples.
+    pub fn max(&self) -> Option<Duration> {
+        if self.count() == 0 {
+            None
+        } else {
+            Some(self.max)
+        }
+    }
+
+    /// Returns the upper bound of the bucket holding the `quantile` (between 0 and 1)
+    /// of the samples, e.g. `0.99` for the 99th percentile. Samples above the last
+    /// bound are reported as the slowest sample. Returns `None` without samples.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, bound) in self.buckets() {
+            seen += bucket;
+            if seen >= rank {
+                return Some(bound.unwrap_or(self.max));
+            }
+        }
+        Some(self.max)
+    }
+
+    /// Returns the number of samples per bucket, with the upper bound of each
+    /// bucket. The last bucket has no upper bound.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, Option<Duration>)> + '_ {
+        self.counts.iter().enumerate().map(|(i, &count)| {
+            let bound = BUCKET_BOUNDS_US.get(i).map(|&us| Duration::from_micros(us));
+            (count, bound)
+        })
+    }
+
+    /// Adds the samples of `other` to this histogram.
+    fn merge(&mut self, other: &LatencyHistogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+}
+
+/// Counters for the commands sent to a RoboClaw.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandStats {
+    pub requests: u64,             // Number of commands sent, without retries
+    pub retries: u64,              // Number of attempts after the first one
+    pub failures: u64,             // Number of commands that failed after all attempts
+    pub timeouts: u64,             // Attempts without a complete response
+    pub crc_errors: u64,           // Attempts whose response failed the CRC check
+    pub nacks: u64,                // Attempts acknowledged with a byte other than 0xFF
+    pub other_errors: u64,         // Attempts failing with an I/O, serial port or delivery error
+    pub latency: LatencyHistogram, // Round-trip time of successful attempts
+}
+
+impl CommandStats {
+    /// Returns the number of failed attempts, whether or not a retry succeeded.
+    pub fn errors(&self) -> u64 {
+        self.timeouts + self.crc_errors + self.nacks + self.other_errors
+    }
+
+    /// Counts a command made of the failed attempts in `history`, followed by a
+    /// successful attempt taking `latency` if it succeeded.
+    fn record(&mut self, history: &[ConnectionError], latency: Option<Duration>) {
+        let attempts = history.len() as u64 + latency.is_some() as u64;
+        self.requests += 1;
+        self.retries += attempts.saturating_sub(1);
+        for error in history {
+            match error {
+                ConnectionError::NoResponse | ConnectionError::ShortResponse { .. } => {
+                    self.timeouts += 1
+                }
+                ConnectionError::CRCMismatch { .. } => self.crc_errors += 1,
+                ConnectionError::BadAck(_) => self.nacks += 1,
+                _ => self.other_errors += 1,
+            }
+        }
+        match latency {
+            Some(latency) => self.latency.record(latency),
+            None => self.failures += 1,
+        }
+    }
+
+    /// Adds the counters of `other` to these counters.
+    fn merge(&mut self, other: &CommandStats) {
+        self.requests += other.requests;
+        self.retries += other.retries;
+        self.failures += other.failures;
+        self.timeouts += other.timeouts;
+        self.crc_errors += other.crc_errors;
+        self.nacks += other.nacks;
+        self.other_errors += other.other_errors;
+        self.latency.merge(&other.latency);
+    }
+}
+
+/// The statistics of a single RoboClaw, per command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    commands: HashMap<Commands, CommandStats>, // Counters per command sent at least once
+}
+
+impl Stats {
+    /// Returns the counters of `command`, or `None` if it was never sent.
+    pub fn command(&self, command: Commands) -> Option<&CommandStats> {
+        self.commands.get(&command)
+    }
+
+    /// Returns the counters of every command sent at least once, in no particular
+    /// order.
+    pub fn commands(&self) -> impl Iterator<Item = (Commands, &CommandStats)> {
+        self.commands
+            .iter()
+            .map(|(command, stats)| (*command, stats))
+    }
+
+    /// Returns the counters summed over all commands.
+    pub fn total(&self) -> CommandStats {
+        let mut total = CommandStats::default();
+        for stats in self.commands.values() {
+            total.merge(stats);
+        }
+        total
+    }
+
+    /// Counts a command, see `CommandStats::record`.
+    pub(crate) fn record(
+        &mut self,
+        command: Commands,
+        history: &[ConnectionError],
+        latency: Option<Duration>,
+    ) {
+        self.commands
+            .entry(command)
+            .or_default()
+            .record(history, latency);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        replay::{
+            tests::{ack, read, write, ADDRESS},
+            ReplayTransport,
+        },
+        Roboclaw,
+    };
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn quantiles_report_the_bucket_bound() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.quantile(0.5), None);
+        assert_eq!(histogram.mean(), None);
+
+        for latency in [1, 1, 1, 3, 3, 8, 15, 15, 40, 900] {
+            histogram.record(millis(latency));
+        }
+        assert_eq!(histogram.count(), 10);
+        assert_eq!(histogram.quantile(0.0), Some(millis(1)));
+        assert_eq!(histogram.quantile(0.3), Some(millis(1)));
+        assert_eq!(histogram.quantile(0.5), Some(millis(5)));
+        assert_eq!(histogram.quantile(0.8), Some(millis(20)));
+        assert_eq!(histogram.quantile(0.9), Some(millis(50)));
+        // The last bucket has no bound and reports the slowest sample.
+        assert_eq!(histogram.quantile(1.0), Some(millis(900)));
+        assert_eq!(histogram.max(), Some(millis(900)));
+        assert_eq!(histogram.mean(), Some(Duration::from_micros(98_700)));
+    }
+
+    #[test]
+    fn failed_attempts_are_counted_by_kind() {
+        let mut stats = CommandStats::default();
+        stats.record(
+            &[
+                ConnectionError::NoResponse,
+                ConnectionError::CRCMismatch {
+                    expected: 1,
+                    actual: 2,
+                },
+            ],
+            Some(millis(2)),
+        );
+        stats.record(
+            &[
+                ConnectionError::BadAck(0x00),
+                ConnectionError::ShortResponse {
+                    expected: 4,
+                    received: 1,
+                },
+                ConnectionError::Unverified,
+            ],
+            None,
+        );
+        assert_eq!(stats.requests, 2);
+        assert_eq!(stats.retries, 4);
+        assert_eq!(stats.failures, 1);
+        assert_eq!(stats.timeouts, 2);
+        assert_eq!(stats.crc_errors, 1);
+        assert_eq!(stats.nacks, 1);
+        assert_eq!(stats.other_errors, 1);
+        assert_eq!(stats.errors(), 5);
+        assert_eq!(stats.latency.count(), 1);
+    }
+
+    #[test]
+    fn connection_counts_retries_per_command() {
+        let mut frames = read(Commands::ReadMainBatVoltage, &[0x00, 0xF0]).to_vec();
+        // The first response is damaged, so the read is retried once.
+        frames[1].bytes[2] ^= 0xFF;
+        frames.extend(read(Commands::ReadMainBatVoltage, &[0x00, 0xF0]));
+        frames.push(write(Commands::ResetEncoders, &[]));
+        frames.push(ack(Commands::ResetEncoders));
+        let mut roboclaw =
+            Roboclaw::from_transport(ReplayTransport::from_frames(frames), ADDRESS).unwrap();
+
+        assert_eq!(roboclaw.read_main_battery_voltage().unwrap(), 0xF0);
+        assert!(roboclaw.reset_encoders().unwrap());
+
+        let battery = roboclaw
+            .stats()
+            .command(Commands::ReadMainBatVoltage)
+            .unwrap();
+        assert_eq!((battery.requests, battery.retries), (1, 1));
+        assert_eq!(battery.crc_errors, 1);
+        let total = roboclaw.stats().total();
+        assert_eq!((total.requests, total.retries, total.errors()), (2, 1, 1));
+        assert_eq!(total.latency.count(), 2);
+    }
+}
@@ -0,0 +1,9 @@
+//! This module holds the locking helper shared by the thread-safe handles.
+
+use std::sync::{Mutex, MutexGuard};
+
+/// Locks `mutex`, ignoring poisoning: a panic in another thread's closure leaves
+/// the connection usable, as every attempt starts from a cleared port.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|error| error.into_inner())
+}
@@ -0,0 +1,315 @@
+//! This module provides a background telemetry poller for a RoboClaw.
+//!
+//! A `Telemetry` service polls a configurable set of `Reading`s, each at its own
+//! interval, and publishes a `Snapshot` of the latest values to every subscriber
+//! after each poll. Every value carries the host times of the exchange it was read
+//! in, see `timestamp::Timestamped`:
+//!
+//! ```no_run
+//! # fn main() -> Result<(), roboclaw::errors::RoboClawError> {
+//! use roboclaw::motor::{Motor, Speed};
+//! use roboclaw::telemetry::{Reading, Telemetry};
+//! use roboclaw::Roboclaw;
+//! use std::time::Duration;
+//!
+//! let roboclaw = Roboclaw::builder().port("/dev/ttyACM0").build()?;
+//! let telemetry = Telemetry::builder()
+//!     .poll(Reading::Encoders, Duration::from_millis(20))
+//!     .poll(Reading::MainBattery, Duration::from_secs(1))
+//!     .spawn(roboclaw);
+//!
+//! let snapshots = telemetry.subscribe();
+//! telemetry
+//!     .roboclaw()
+//!     .with(|roboclaw| roboclaw.send(Motor::Both, Speed { speed: 1000 }))?;
+//! for snapshot in snapshots.iter().take(10) {
+//!     println!("{:?}", snapshot.encoders);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The RoboClaw is held in a `SharedRoboclaw`, so commands can be sent through
+//! `Telemetry::roboclaw` while polling. Every reading is a separate command on the
+//! shared connection, so a command waits for at most one reading.
+
+use crate::{
+    errors::RoboClawError, shared::SharedRoboclaw, sync::lock, timestamp::Timestamped,
+    BufferStatus, Roboclaw, StatusFlags,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// The shortest interval a reading is polled at.
+pub const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A value the poller can read from the RoboClaw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reading {
+    Encoders,     // Encoder counts, see `Roboclaw::read_encoders`
+    Speeds,       // Motor speeds, see `Roboclaw::read_speeds`
+    Currents,     // Motor currents, see `Roboclaw::read_currents`
+    MainBattery,  // Main battery voltage, see `Roboclaw::read_main_battery_voltage`
+    LogicBattery, // Logic battery voltage, see `Roboclaw::read_logic_battery_voltage`
+    Temperature,  // Board temperature, see `Roboclaw::read_temperature`
+    Temperature2, // Second temperature sensor, see `Roboclaw::read_temperature_2`
+    Status,       // Status flags, see `Roboclaw::read_error`
+    Buffers,      // Command buffer depths, see `Roboclaw::read_buffers`
+}
+
+/// The latest value of every polled reading. Readings not polled yet are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub encoders: Option<Timestamped<[u32; 2]>>,
+    pub speeds: Option<Timestamped<[i32; 2]>>,
+    pub currents: Option<Timestamped<[i16; 2]>>,
+    pub main_battery: Option<Timestamped<u32>>,
+    pub logic_battery: Option<Timestamped<u32>>,
+    pub temperature: Option<Timestamped<u16>>,
+    pub temperature_2: Option<Timestamped<u16>>,
+    pub status: Option<Timestamped<StatusFlags>>,
+    pub buffers: Option<Timestamped<[BufferStatus; 2]>>,
+    pub updated: Vec<Reading>, // Readings read successfully by the last poll
+    pub failed: Vec<Reading>,  // Readings that failed in the last poll, keeping their older value
+}
+
+impl Snapshot {
+    /// Reads `reading` from `roboclaw` and stores it with the times of the exchange.
+    fn poll(&mut self, roboclaw: &SharedRoboclaw, reading: Reading) -> Result<(), RoboClawError> {
+        roboclaw.with(|roboclaw| {
+            match reading {
+                Reading::Encoders => {
+                    self.encoders = Some(roboclaw.timestamped(Roboclaw::read_encoders)?)
+                }
+                Reading::Speeds => self.speeds = Some(roboclaw.timestamped(Roboclaw::read_speeds)?),
+                Reading::Currents => {
+                    self.currents = Some(roboclaw.timestamped(Roboclaw::read_currents)?)
+                }
+                Reading::MainBattery => {
+                    self.main_battery =
+                        Some(roboclaw.timestamped(Roboclaw::read_main_battery_voltage)?)
+                }
+                Reading::LogicBattery => {
+                    self.logic_battery =
+                        Some(roboclaw.timestamped(Roboclaw::read_logic_battery_voltage)?)
+                }
+                Reading::Temperature => {
+                    self.temperature = Some(roboclaw.timestamped(Roboclaw::read_temperature)?)
+                }
+                Reading::Temperature2 => {
+                    self.temperature_2 = Some(roboclaw.timestamped(Roboclaw::read_temperature_2)?)
+                }
+                Reading::Status => self.status = Some(roboclaw.timestamped(Roboclaw::read_error)?),
+                Reading::Buffers => {
+                    self.buffers = Some(roboclaw.timestamped(Roboclaw::read_buffers)?)
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Configures and starts a `Telemetry` service.
+#[derive(Debug, Clone, Default)]
+pub struct TelemetryBuilder {
+    schedule: Vec<(Reading, Duration)>, // Readings to poll with their intervals
+}
+
+impl TelemetryBuilder {
+    /// Polls `reading` every `interval`. Polling a reading again replaces its
+    /// interval. Intervals below `MIN_INTERVAL` are raised to it, so the poller
+    /// never spins without sleeping.
+    pub fn poll(mut self, reading: Reading, interval: Duration) -> Self {
+        self.schedule.retain(|(scheduled, _)| *scheduled != reading);
+        self.schedule.push((reading, interval.max(MIN_INTERVAL)));
+        self
+    }
+
+    /// Starts polling `roboclaw` on a background thread.
+    pub fn spawn<R: Into<SharedRoboclaw>>(self, roboclaw: R) -> Telemetry {
+        let roboclaw = roboclaw.into();
+        let state = Arc::new(State {
+            subscribers: Mutex::new(Vec::new()),
+            latest: Mutex::new(Snapshot::default()),
+            stop: AtomicBool::new(false),
+        });
+        let thread = {
+            let roboclaw = roboclaw.clone();
+            let state = Arc::clone(&state);
+            thread::spawn(move || run(&roboclaw, &state, self.schedule))
+        };
+
+        Telemetry {
+            roboclaw,
+            state,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// The state shared between a `Telemetry` handle and its thread.
+struct State {
+    subscribers: Mutex<Vec<Sender<Snapshot>>>, // Channels receiving every snapshot
+    latest: Mutex<Snapshot>,                   // The last snapshot published
+    stop: AtomicBool,                          // Set to request the thread to exit
+}
+
+/// Handle to a running telemetry poller, see the module documentation. The poller
+/// is stopped when the handle is dropped.
+pub struct Telemetry {
+    roboclaw: SharedRoboclaw,       // The polled RoboClaw
+    state: Arc<State>,              // Subscribers and latest snapshot
+    thread: Option<JoinHandle<()>>, // The polling thread, taken when joining
+}
+
+impl Telemetry {
+    /// Returns a builder without any readings to poll.
+    pub fn builder() -> TelemetryBuilder {
+        TelemetryBuilder::default()
+    }
+
+    /// Returns the handle to send commands to the polled RoboClaw.
+    pub fn roboclaw(&self) -> &SharedRoboclaw {
+        &self.roboclaw
+    }
+
+    /// Returns a channel receiving a `Snapshot` after every poll. Snapshots queue
+    /// up in the channel until received; dropping the receiver unsubscribes.
+    pub fn subscribe(&self) -> Receiver<Snapshot> {
+        let (sender, receiver) = mpsc::channel();
+        lock(&self.state.subscribers).push(sender);
+        receiver
+    }
+
+    /// Returns the last snapshot published.
+    pub fn latest(&self) -> Snapshot {
+        lock(&self.state.latest).clone()
+    }
+
+    /// Stops polling and waits for the thread to exit. Returns the handle to the
+    /// RoboClaw for further use.
+    pub fn stop(mut self) -> SharedRoboclaw {
+        self.shutdown();
+        self.roboclaw.clone()
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.state.stop.store(true, Ordering::Release);
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// The polling loop: polls every reading that is due, publishes the snapshot and
+/// sleeps until the next reading is due.
+fn run(roboclaw: &SharedRoboclaw, state: &State, schedule: Vec<(Reading, Duration)>) {
+    let start = Instant::now();
+    let mut due: Vec<(Reading, Duration, Instant)> = schedule
+        .into_iter()
+        .map(|(reading, interval)| (reading, interval, start))
+        .collect();
+    let mut snapshot = Snapshot::default();
+
+    while !state.stop.load(Ordering::Acquire) {
+        let now = Instant::now();
+        snapshot.updated.clear();
+        snapshot.failed.clear();
+        for (reading, interval, next) in due.iter_mut().filter(|(_, _, next)| *next <= now) {
+            match snapshot.poll(roboclaw, *reading) {
+                Ok(()) => snapshot.updated.push(*reading),
+                Err(_) => snapshot.failed.push(*reading),
+            }
+            // Skip polls missed while the connection was slow instead of
+            // catching up with a burst.
+            *next = (*next + *interval).max(now);
+        }
+
+        if !snapshot.updated.is_empty() || !snapshot.failed.is_empty() {
+            *lock(&state.latest) = snapshot.clone();
+            lock(&state.subscribers).retain(|sender| sender.send(snapshot.clone()).is_ok());
+        }
+
+        match due.iter().map(|(_, _, next)| *next).min() {
+            Some(next) => thread::park_timeout(next.saturating_duration_since(Instant::now())),
+            None => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        replay::{tests::*, ReplayTransport},
+        Commands,
+    };
+
+    #[test]
+    fn intervals_are_replaced_and_clamped() {
+        let builder = Telemetry::builder()
+            .poll(Reading::Encoders, Duration::from_secs(1))
+            .poll(Reading::MainBattery, Duration::ZERO)
+            .poll(Reading::Encoders, Duration::from_millis(20));
+        assert_eq!(
+            builder.schedule,
+            [
+                (Reading::MainBattery, MIN_INTERVAL),
+                (Reading::Encoders, Duration::from_millis(20)),
+            ]
+        );
+    }
+
+    #[test]
+    fn subscribers_receive_a_snapshot_per_poll() {
+        let mut frames = Vec::new();
+        frames.extend(read(Commands::ReadEncoderCounts, &[0, 0, 0, 7, 0, 0, 0, 9]));
+        frames.extend(read(Commands::ReadMainBatVoltage, &[0, 120]));
+        frames.extend(read(Commands::ReadMainBatVoltage, &[0, 119]));
+        let transport = ReplayTransport::from_frames(frames);
+        let shared = SharedRoboclaw::from(Roboclaw::from_transport(transport, ADDRESS).unwrap());
+
+        // Holding the RoboClaw keeps the first poll from starting before subscribing.
+        let interval = Duration::from_millis(20);
+        let spawned = Instant::now();
+        let (telemetry, snapshots) = shared.with(|_| {
+            let telemetry = Telemetry::builder()
+                .poll(Reading::Encoders, Duration::from_secs(3600))
+                .poll(Reading::MainBattery, interval)
+                .spawn(shared.clone());
+            let snapshots = telemetry.subscribe();
+            (telemetry, snapshots)
+        });
+
+        // Every reading is due at the start.
+        let first = snapshots.recv().unwrap();
+        assert_eq!(first.updated, [Reading::Encoders, Reading::MainBattery]);
+        assert!(first.failed.is_empty());
+        assert_eq!(first.encoders.unwrap().value, [7, 9]);
+        let polled = first.main_battery.unwrap();
+        assert_eq!(polled.value, 120);
+
+        // Only the battery is due again, the encoders keep their value.
+        let second = snapshots.recv().unwrap();
+        assert_eq!(second.updated, [Reading::MainBattery]);
+        assert_eq!(second.encoders, first.encoders);
+        let repolled = second.main_battery.unwrap();
+        assert_eq!(repolled.value, 119);
+        assert!(repolled.sent >= spawned + interval);
+        telemetry.stop();
+    }
+}
@@ -0,0 +1,96 @@
+//! This module defines `Timestamped`, a value read from the RoboClaw together with
+//! the host's monotonic clock readings around the exchange that produced it.
+//!
+//! The RoboClaw does not report when it sampled a value. It answers a read as soon
+//! as the request arrived, so the sample was taken somewhere between the request
+//! being sent and the response being received. `Timestamped::midpoint` estimates
+//! that instant; `Timestamped::uncertainty` bounds the error of the estimate. Use
+//! `Roboclaw::timestamped` to timestamp any read.
+
+use std::time::{Duration, Instant};
+
+/// A value with the host times of the exchange it was read in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamped<T> {
+    pub value: T,          // The value read
+    pub sent: Instant,     // When the request was written to the port
+    pub received: Instant, // When the last byte of the response was read
+}
+
+impl<T> Timestamped<T> {
+    /// Returns the time between sending the request and receiving the response.
+    pub fn round_trip(&self) -> Duration {
+        self.received.saturating_duration_since(self.sent)
+    }
+
+    /// Returns the estimated time the RoboClaw sampled the value, halfway between
+    /// sending the request and receiving the response.
+    pub fn midpoint(&self) -> Instant {
+        self.sent + self.round_trip() / 2
+    }
+
+    /// Returns how far the sampling time can be from `midpoint`: half the round
+    /// trip.
+    pub fn uncertainty(&self) -> Duration {
+        self.round_trip() / 2
+    }
+
+    /// Converts the value, keeping the timestamps.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Timestamped<U> {
+        Timestamped {
+            value: f(self.value),
+            sent: self.sent,
+            received: self.received,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(round_trip: Duration) -> Timestamped<u16> {
+        let sent = Instant::now();
+        Timestamped {
+            value: 120,
+            sent,
+            received: sent + round_trip,
+        }
+    }
+
+    #[test]
+    fn midpoint_is_halfway_through_the_round_trip() {
+        let reading = reading(Duration::from_micros(3001));
+        assert_eq!(reading.round_trip(), Duration::from_micros(3001));
+        assert_eq!(
+            reading.midpoint(),
+            reading.sent + Duration::from_nanos(1_500_500)
+        );
+        assert_eq!(reading.uncertainty(), Duration::from_nanos(1_500_500));
+        // The sampling time is within the uncertainty of the midpoint either way.
+        assert_eq!(reading.midpoint() - reading.uncertainty(), reading.sent);
+        assert_eq!(reading.midpoint() + reading.uncertainty(), reading.received);
+    }
+
+    #[test]
+    fn received_before_sent_counts_as_no_round_trip() {
+        let mut reading = reading(Duration::ZERO);
+        assert_eq!(reading.midpoint(), reading.sent);
+        assert_eq!(reading.uncertainty(), Duration::ZERO);
+
+        reading.sent += Duration::from_millis(1);
+        assert_eq!(reading.round_trip(), Duration::ZERO);
+        assert_eq!(reading.midpoint(), reading.sent);
+    }
+
+    #[test]
+    fn map_keeps_the_timestamps() {
+        let reading = reading(Duration::from_millis(2));
+        let volts = reading.map(|raw| raw as f32 / 10.0);
+        assert_eq!(volts.value, 12.0);
+        assert_eq!(
+            (volts.sent, volts.received),
+            (reading.sent, reading.received)
+        );
+    }
+}
@@ -0,0 +1,380 @@
+//! This module provides an opt-in trace of the raw packets exchanged with the
+//! RoboClaw.
+//!
+//! A `TraceSink` set with `Roboclaw::set_trace` receives every transmitted and
+//! received `Frame`, stamped with the time it was sent or received. `TraceWriter`
+//! writes the frames to a file, one line per frame:
+//!
+//! ```text
+//! 1697040000.123456 tx 0x80 37 8025000004b0fffffb500d95 # addr 0x80 MixDriveSignedSpeed speed1=1200 speed2=-1200 crc=OK
+//! 1697040000.124012 rx 0x80 37 ff # addr 0x80 MixDriveSignedSpeed ack
+//! ```
+//!
+//! The fields before `#` are the seconds since the Unix epoch with microsecond
+//! precision, the direction, the controller address, the command number and the
+//! frame bytes in hex. They form the stable part of the format. Everything after `#`
+//! is the decoded frame as rendered by `Frame`'s `Display` implementation, which is
+//! meant for humans and may change. `Frame::from_line` reads the stable part back,
+//! `replay::ReplayTransport` uses it to replay a recorded session.
+
+use crate::{
+    codec::MAX_STRING_LEN,
+    schema::{Field, FieldType, Reply},
+    Commands,
+};
+use crc16::{State, XMODEM};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, LineWriter, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The direction of a frame, seen from the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Tx, // Sent to the RoboClaw
+    Rx, // Received from the RoboClaw
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Tx => write!(f, "tx"),
+            Direction::Rx => write!(f, "rx"),
+        }
+    }
+}
+
+/// A single packet as it was on the wire.
+///
+/// A transmitted frame holds the whole request, starting with the address and
+/// command byte. A received frame holds the response bytes of one attempt, which
+/// may be incomplete if the RoboClaw stopped answering.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub timestamp: SystemTime, // Time the frame was sent, or its last byte received
+    pub direction: Direction,  // Whether the frame was sent or received
+    pub address: u8,           // Address of the RoboClaw the frame was exchanged with
+    pub command: Commands,     // The command the frame belongs to
+    pub bytes: Vec<u8>,        // The raw bytes of the frame
+}
+
+impl Frame {
+    /// Renders the frame as a line of the trace file format, without a line break.
+    pub fn to_line(&self) -> String {
+        let timestamp = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut line = format!(
+            "{}.{:06} {} {:#04x} {} ",
+            timestamp.as_secs(),
+            timestamp.subsec_micros(),
+            self.direction,
+            self.address,
+            self.command as u8
+        );
+        for byte in &self.bytes {
+            line.push_str(&format!("{:02x}", byte));
+        }
+        line.push_str(&format!(" # {}", self));
+        line
+    }
+
+    /// Parses a line of the trace file format. Only the stable part before `#` is
+    /// read. Returns `None` if the line is not a valid frame.
+    pub fn from_line(line: &str) -> Option<Frame> {
+        let line = line.split('#').next()?;
+        let mut parts = line.split_whitespace();
+
+        // The fraction is always written with six digits, so anything else is a
+        // damaged line rather than fewer microseconds.
+        let (secs, micros) = parts.next()?.split_once('.')?;
+        if micros.len() != 6 || !micros.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        let timestamp = UNIX_EPOCH
+            .checked_add(Duration::from_secs(secs.parse().ok()?))?
+            .checked_add(Duration::from_micros(micros.parse().ok()?))?;
+        let direction = match parts.next()? {
+            "tx" => Direction::Tx,
+            "rx" => Direction::Rx,
+            _ => return None,
+        };
+        let address = u8::from_str_radix(parts.next()?.strip_prefix("0x")?, 16).ok()?;
+        let command = Commands::from_u8(parts.next()?.parse().ok()?)?;
+        let hex = parts.next().unwrap_or_default();
+        if hex.len() % 2 != 0 || parts.next().is_some() {
+            return None;
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+
+        Some(Frame {
+            timestamp,
+            direction,
+            address,
+            command,
+            bytes,
+        })
+    }
+
+    /// Renders a transmitted frame: the request fields, followed by the CRC for
+    /// commands acknowledged with `0xFF`.
+    fn fmt_request(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let schema = self.command.schema();
+        let payload = self.bytes.get(2..).unwrap_or_default();
+        let end = match fmt_fields(f, schema.request, payload)? {
+            Some(end) => end,
+            None => return write!(f, " truncated"),
+        };
+        if schema.reply == Reply::Ack {
+            // A frame too short to hold the address and command byte has no CRC to check.
+            match self.bytes.get(..end + 2) {
+                Some(covered) => fmt_crc(f, covered, &payload[end..])?,
+                None => return write!(f, " truncated"),
+            }
+        } else if end < payload.len() {
+            write!(f, " trailing={}", payload.len() - end)?;
+        }
+        Ok(())
+    }
+
+    /// Renders a received frame: the acknowledgement, or the response fields and
+    /// their CRC.
+    fn fmt_response(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let schema = self.command.schema();
+        let fields = match (schema.reply, schema.legacy_reply) {
+            (Reply::Ack, _) => {
+                return match self.bytes.as_slice() {
+                    [0xFF] => write!(f, " ack"),
+                    [] => write!(f, " no response"),
+                    [ack, ..] => write!(f, " nack={:#04x}", ack),
+                };
+            }
+            (Reply::Data(_), Some(Reply::Data(legacy)))
+                if fixed_size(legacy) == Some(self.bytes.len().saturating_sub(2)) =>
+            {
+                legacy
+            }
+            (Reply::Data(fields), _) => fields,
+        };
+        let end = match fmt_fields(f, fields, &self.bytes)? {
+            Some(end) => end,
+            None => return write!(f, " truncated"),
+        };
+        if !schema.request.is_empty() {
+            // The CRC also covers the request arguments, which this frame lacks.
+            return match &self.bytes[end..] {
+                [high, low] => write!(f, " crc={:#06x}", u16::from_be_bytes([*high, *low])),
+                _ => fmt_crc(f, &[], &self.bytes[end..]),
+            };
+        }
+        let mut covered = vec![self.address, self.command as u8];
+        covered.extend_from_slice(&self.bytes[..end]);
+        fmt_crc(f, &covered, &self.bytes[end..])
+    }
+}
+
+impl fmt::Display for Frame {
+    /// Renders the decoded frame, e.g.
+    /// `addr 0x80 MixDriveSignedSpeed speed1=1200 speed2=-1200 crc=OK`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "addr {:#04x} {:?}", self.address, self.command)?;
+        match self.direction {
+            Direction::Tx => self.fmt_request(f),
+            Direction::Rx => self.fmt_response(f),
+        }
+    }
+}
+
+/// Returns the wire size of `fields`, or `None` if it contains a string.
+fn fixed_size(fields: &[Field]) -> Option<usize> {
+    fields.iter().map(|field| field.ty.size()).sum()
+}
+
+/// Writes ` name=value` for every field decoded from `data`. Returns the number of
+/// bytes the fields took, or `None` if `data` ended before the last field.
+fn fmt_fields(
+    f: &mut fmt::Formatter<'_>,
+    fields: &[Field],
+    data: &[u8],
+) -> Result<Option<usize>, fmt::Error> {
+    let mut offset = 0;
+    for field in fields {
+        let rest = &data[offset..];
+        let size = match field.ty.size() {
+            Some(size) => size,
+            None => rest
+                .iter()
+                .take(MAX_STRING_LEN)
+                .position(|&byte| byte == 0)
+                .map_or(rest.len().min(MAX_STRING_LEN), |nul| nul + 1),
+        };
+        if rest.len() < size {
+            return Ok(None);
+        }
+        let bytes = &rest[..size];
+        write!(f, " {}=", field.name)?;
+        match field.ty {
+            FieldType::U8 => write!(f, "{}", bytes[0])?,
+            FieldType::I8 => write!(f, "{}", bytes[0] as i8)?,
+            FieldType::Bool => write!(f, "{}", bytes[0] != 0)?,
+            FieldType::U16 => write!(f, "{}", u16::from_be_bytes([bytes[0], bytes[1]]))?,
+            FieldType::I16 => write!(f, "{}", i16::from_be_bytes([bytes[0], bytes[1]]))?,
+            FieldType::U32 => write!(
+                f,
+                "{}",
+                u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+            )?,
+            FieldType::I32 => write!(
+                f,
+                "{}",
+                i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+            )?,
+            FieldType::String => {
+                let text = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+                write!(f, "{:?}", String::from_utf8_lossy(text))?
+            }
+        }
+        offset += size;
+    }
+    Ok(Some(offset))
+}
+
+/// Writes the state of the CRC in `crc` computed over `covered`.
+fn fmt_crc(f: &mut fmt::Formatter<'_>, covered: &[u8], crc: &[u8]) -> fmt::Result {
+    let expected = State::<XMODEM>::calculate(covered);
+    match crc {
+        [high, low] if u16::from_be_bytes([*high, *low]) == expected => write!(f, " crc=OK"),
+        [high, low] => write!(
+            f,
+            " crc=BAD({:#06x}, expected {:#06x})",
+            u16::from_be_bytes([*high, *low]),
+            expected
+        ),
+        [] | [_] => write!(f, " crc=missing"),
+        [_, _, rest @ ..] => write!(f, " crc=? trailing={}", rest.len()),
+    }
+}
+
+/// Receives the frames exchanged with the RoboClaw.
+///
+/// Sinks are called from the thread using the `Roboclaw`, in the middle of a
+/// command, so they should return quickly.
+pub trait TraceSink: Send {
+    /// Records a frame.
+    fn record(&mut self, frame: &Frame);
+}
+
+impl<F: FnMut(&Frame) + Send> TraceSink for F {
+    fn record(&mut self, frame: &Frame) {
+        self(frame)
+    }
+}
+
+/// A `TraceSink` writing frames in the trace file format.
+///
+/// Write errors are ignored, so that a full disk never interrupts motor control.
+pub struct TraceWriter<W: Write + Send> {
+    writer: W, // Destination of the trace lines
+}
+
+impl TraceWriter<LineWriter<File>> {
+    /// Creates or truncates the trace file at `path`. Every frame is flushed as soon
+    /// as its line is complete.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(TraceWriter::new(LineWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write + Send> TraceWriter<W> {
+    /// Creates a trace writer on top of `writer`.
+    pub fn new(writer: W) -> Self {
+        TraceWriter { writer }
+    }
+}
+
+impl<W: Write + Send> TraceSink for TraceWriter<W> {
+    fn record(&mut self, frame: &Frame) {
+        let _ = writeln!(self.writer, "{}", frame.to_line());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::tests::{read, write};
+
+    #[test]
+    fn frames_round_trip_through_trace_lines() {
+        let [request, response] = read(
+            Commands::ReadEncoderCounts,
+            &[0, 0, 0x01, 0, 0xFF, 0xFF, 0xFF, 0xFE],
+        );
+        let sent = write(
+            Commands::MixDriveSignedSpeed,
+            &[0, 0, 0x04, 0xB0, 0xFF, 0xFF, 0xFB, 0x50],
+        );
+        for frame in [request, response, sent] {
+            let frame = Frame {
+                timestamp: UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456),
+                ..frame
+            };
+            assert_eq!(Frame::from_line(&frame.to_line()), Some(frame));
+        }
+    }
+
+    #[test]
+    fn frames_are_decoded() {
+        let sent = write(
+            Commands::MixDriveSignedSpeed,
+            &[0, 0, 0x04, 0xB0, 0xFF, 0xFF, 0xFB, 0x50],
+        );
+        assert_eq!(
+            sent.to_string(),
+            "addr 0x80 MixDriveSignedSpeed speed1=1200 speed2=-1200 crc=OK"
+        );
+        let [_, response] = read(
+            Commands::ReadEncoderCounts,
+            &[0, 0, 0x01, 0, 0xFF, 0xFF, 0xFF, 0xFE],
+        );
+        assert!(response.to_string().ends_with("crc=OK"), "{}", response);
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        assert_eq!(Frame::from_line(""), None);
+        assert_eq!(Frame::from_line("1.000000 up 0x80 20"), None);
+        assert_eq!(Frame::from_line("1.000000 tx 0x80 20 801"), None);
+        assert_eq!(Frame::from_line("1.000000 tx 0x80 20 80 14"), None);
+    }
+
+    #[test]
+    fn fractions_need_six_digits() {
+        let frame = Frame::from_line("1.500000 tx 0x80 20").unwrap();
+        assert_eq!(frame.timestamp, UNIX_EPOCH + Duration::from_millis(1500));
+        for line in [
+            "1.5 tx 0x80 20",
+            "1.50000 tx 0x80 20",
+            "1.5000000 tx 0x80 20",
+            "1.+50000 tx 0x80 20",
+        ] {
+            assert_eq!(Frame::from_line(line), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn truncated_requests_do_not_panic() {
+        for line in ["1.000000 tx 0x80 20", "1.000000 tx 0x80 20 80"] {
+            let frame = Frame::from_line(line).unwrap();
+            assert_eq!(frame.to_string(), "addr 0x80 ResetEncoders truncated");
+        }
+        let frame = Frame::from_line("1.000000 tx 0x80 35 8023").unwrap();
+        assert!(frame.to_string().ends_with("truncated"), "{}", frame);
+    }
+}
@@ -0,0 +1,57 @@
+//! This module defines the `Transport` the connection exchanges bytes over.
+//!
+//! A serial port is the usual transport, `Box<dyn SerialPort>` implements the
+//! trait. Other implementations, such as `replay::ReplayTransport`, allow running a
+//! `Roboclaw` without hardware through `Roboclaw::from_transport`, or
+//! `reconnect::ReconnectingTransport` to survive a USB disconnect.
+
+use serialport::{ClearBuffer, SerialPort};
+use std::{io, time::Duration};
+
+/// A byte stream to a RoboClaw, modelled after the parts of `SerialPort` the
+/// connection uses.
+pub trait Transport: Send {
+    /// Writes all of `bytes`.
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    /// Reads up to `buffer.len()` bytes. Reports `ErrorKind::TimedOut` if nothing
+    /// arrives within the timeout.
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize>;
+
+    /// Sets the read timeout.
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()>;
+
+    /// Discards all bytes not yet read or written.
+    fn clear(&mut self) -> serialport::Result<()>;
+
+    /// Returns the number of bytes available to read without waiting.
+    fn bytes_to_read(&mut self) -> serialport::Result<u32>;
+
+    /// Returns `true` if the transport can be used. Transports that reconnect
+    /// may try to reopen their port here.
+    fn ensure_connected(&mut self) -> bool {
+        true
+    }
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        io::Write::write_all(self, bytes)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        io::Read::read(self, buffer)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        SerialPort::set_timeout(self.as_mut(), timeout)
+    }
+
+    fn clear(&mut self) -> serialport::Result<()> {
+        SerialPort::clear(self.as_ref(), ClearBuffer::All)
+    }
+
+    fn bytes_to_read(&mut self) -> serialport::Result<u32> {
+        SerialPort::bytes_to_read(self.as_ref())
+    }
+}
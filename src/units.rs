@@ -0,0 +1,528 @@
+//! This module provides an optional layer of physical units on top of the raw
+//! integers the RoboClaw uses: encoder counts, counts per second, tenths of a volt,
+//! units of 10 mA and tenths of a degree.
+//!
+//! Quantities are newtypes around `f64`, so a speed cannot be passed where a
+//! distance is expected. A `Wheel` describes the encoder, gearbox and wheel of a
+//! motor channel and converts between counts and metres or radians.
+//! `Roboclaw::units` returns a `Units` view that sends and reads physical
+//! quantities:
+//!
+//! ```no_run
+//! # fn main() -> Result<(), roboclaw::errors::RoboClawError> {
+//! use roboclaw::motor::Motor;
+//! use roboclaw::units::{Meters, MetersPerSecond, Wheel};
+//! use roboclaw::Roboclaw;
+//!
+//! let mut roboclaw = Roboclaw::builder().port("/dev/ttyACM0").build()?;
+//! let wheel = Wheel::new(2048.0, 19.2, Meters(0.05));
+//! let mut units = roboclaw.units([wheel, wheel]);
+//! units.set_speed(Motor::Both, MetersPerSecond(0.5))?;
+//! let voltage = units.read_main_battery()?;
+//! let [left, right] = units.read_currents()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    errors::{OutOfRange, RoboClawError},
+    motor::{carrier, Motor, MotorCommand, Speed, SpeedAccel, SpeedAccelDistance},
+    Roboclaw,
+};
+use std::{
+    f64::consts::PI,
+    fmt,
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+macro_rules! unit {
+    ($(#[$doc:meta])* $name:ident, $symbol:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+        pub struct $name(pub f64);
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Display::fmt(&self.0, f)?;
+                write!(f, " {}", $symbol)
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+
+            fn add(self, other: $name) -> $name {
+                $name(self.0 + other.0)
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+
+            fn sub(self, other: $name) -> $name {
+                $name(self.0 - other.0)
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+
+            fn neg(self) -> $name {
+                $name(-self.0)
+            }
+        }
+
+        impl Mul<f64> for $name {
+            type Output = $name;
+
+            fn mul(self, factor: f64) -> $name {
+                $name(self.0 * factor)
+            }
+        }
+
+        impl Div<f64> for $name {
+            type Output = $name;
+
+            fn div(self, divisor: f64) -> $name {
+                $name(self.0 / divisor)
+            }
+        }
+    };
+}
+
+unit!(
+    /// A distance in metres.
+    Meters,
+    "m"
+);
+unit!(
+    /// A linear speed in metres per second.
+    MetersPerSecond,
+    "m/s"
+);
+unit!(
+    /// A linear acceleration in metres per second squared.
+    MetersPerSecondSquared,
+    "m/s²"
+);
+unit!(
+    /// An angle in radians.
+    Radians,
+    "rad"
+);
+unit!(
+    /// An angular speed in radians per second.
+    RadiansPerSecond,
+    "rad/s"
+);
+unit!(
+    /// A current in amperes.
+    Amps,
+    "A"
+);
+unit!(
+    /// A voltage in volts.
+    Volts,
+    "V"
+);
+unit!(
+    /// A temperature in degrees Celsius.
+    Celsius,
+    "°C"
+);
+
+impl Amps {
+    /// Converts a current in the RoboClaw's units of 10 mA.
+    pub fn from_raw(current: i16) -> Self {
+        Amps(current as f64 / 100.0)
+    }
+}
+
+impl Volts {
+    /// Converts a voltage in the RoboClaw's units of a tenth of a volt.
+    pub fn from_raw(voltage: u32) -> Self {
+        Volts(voltage as f64 / 10.0)
+    }
+}
+
+impl Celsius {
+    /// Converts a temperature in the RoboClaw's units of a tenth of a degree.
+    pub fn from_raw(temperature: u16) -> Self {
+        Celsius(temperature as f64 / 10.0)
+    }
+}
+
+/// The encoder, gearbox and wheel driven by a motor channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wheel {
+    pub counts_per_revolution: f64, // Encoder counts per revolution of the motor shaft
+    pub gear_ratio: f64,            // Motor revolutions per revolution of the wheel
+    pub radius: Meters,             // Radius of the wheel
+}
+
+impl Wheel {
+    /// Describes a wheel of `radius` driven through `gear_ratio` by a motor with
+    /// `counts_per_revolution` encoder counts per revolution.
+    pub fn new(counts_per_revolution: f64, gear_ratio: f64, radius: Meters) -> Self {
+        Wheel {
+            counts_per_revolution,
+            gear_ratio,
+            radius,
+        }
+    }
+
+    /// Returns the encoder counts per revolution of the wheel.
+    pub fn counts_per_wheel_revolution(&self) -> f64 {
+        self.counts_per_revolution * self.gear_ratio
+    }
+
+    /// Returns the encoder counts per metre travelled.
+    pub fn counts_per_meter(&self) -> f64 {
+        self.counts_per_wheel_revolution() / (2.0 * PI * self.radius.0)
+    }
+
+    /// Returns the distance travelled for `counts` encoder counts.
+    pub fn distance(&self, counts: i64) -> Meters {
+        Meters(counts as f64 / self.counts_per_meter())
+    }
+
+    /// Returns the speed for `counts_per_second`.
+    pub fn speed(&self, counts_per_second: i32) -> MetersPerSecond {
+        MetersPerSecond(counts_per_second as f64 / self.counts_per_meter())
+    }
+
+    /// Returns the angle the wheel turned for `counts` encoder counts.
+    pub fn angle(&self, counts: i64) -> Radians {
+        Radians(counts as f64 * 2.0 * PI / self.counts_per_wheel_revolution())
+    }
+
+    /// Returns the angular speed of the wheel for `counts_per_second`.
+    pub fn angular_speed(&self, counts_per_second: i32) -> RadiansPerSecond {
+        RadiansPerSecond(counts_per_second as f64 * 2.0 * PI / self.counts_per_wheel_revolution())
+    }
+
+    /// Converts `distance` to encoder counts, rounded to the nearest count.
+    pub fn distance_counts(&self, distance: Meters) -> f64 {
+        (distance.0 * self.counts_per_meter()).round()
+    }
+
+    /// Converts `speed` to counts per second, rounded to the nearest count.
+    pub fn speed_counts(&self, speed: MetersPerSecond) -> f64 {
+        (speed.0 * self.counts_per_meter()).round()
+    }
+
+    /// Converts `accel` to counts per second squared, rounded to the nearest count.
+    pub fn accel_counts(&self, accel: MetersPerSecondSquared) -> f64 {
+        (accel.0 * self.counts_per_meter()).round()
+    }
+}
+
+/// A `Roboclaw` sending and reading physical quantities, see the module
+/// documentation.
+pub struct Units<'a> {
+    roboclaw: &'a mut Roboclaw, // The RoboClaw the quantities are sent to
+    wheels: [Wheel; 2],         // The wheels of M1 and M2
+}
+
+impl<'a> Units<'a> {
+    pub(crate) fn new(roboclaw: &'a mut Roboclaw, wheels: [Wheel; 2]) -> Self {
+        Units { roboclaw, wheels }
+    }
+
+    /// Drives `motor` at `speed` with the speed PID.
+    pub fn set_speed(
+        &mut self,
+        motor: Motor,
+        speed: MetersPerSecond,
+    ) -> Result<bool, RoboClawError> {
+        self.set_speeds_of(motor, speed, speed)
+    }
+
+    /// Drives M1 at `m1` and M2 at `m2` with the speed PID.
+    pub fn set_speeds(
+        &mut self,
+        m1: MetersPerSecond,
+        m2: MetersPerSecond,
+    ) -> Result<bool, RoboClawError> {
+        self.set_speeds_of(Motor::Both, m1, m2)
+    }
+
+    fn set_speeds_of(
+        &mut self,
+        motor: Motor,
+        m1: MetersPerSecond,
+        m2: MetersPerSecond,
+    ) -> Result<bool, RoboClawError> {
+        let speeds = [m1, m2];
+        self.send(motor, |index, wheel| {
+            Ok(Speed {
+                speed: speed_counts(wheel, speeds[index])?,
+            })
+        })
+    }
+
+    /// Accelerates `motor` at `accel` to `speed`.
+    pub fn set_speed_accel(
+        &mut self,
+        motor: Motor,
+        speed: MetersPerSecond,
+        accel: MetersPerSecondSquared,
+    ) -> Result<bool, RoboClawError> {
+        self.send(motor, |_, wheel| {
+            Ok(SpeedAccel {
+                accel: accel_counts(wheel, accel)?,
+                speed: speed_counts(wheel, speed)?,
+            })
+        })
+    }
+
+    /// Accelerates `motor` at `accel` to `speed` and drives `distance`, which is
+    /// travelled backwards for a negative speed.
+    pub fn drive_distance(
+        &mut self,
+        motor: Motor,
+        speed: MetersPerSecond,
+        accel: MetersPerSecondSquared,
+        distance: Meters,
+        execute_directly: bool,
+    ) -> Result<bool, RoboClawError> {
+        self.send(motor, |_, wheel| {
+            Ok(SpeedAccelDistance {
+                accel: accel_counts(wheel, accel)?,
+                speed: speed_counts(wheel, speed)?,
+                distance: distance_counts(wheel, distance)?,
+                execute_directly,
+            })
+        })
+    }
+
+    /// Reads the distance each wheel travelled since the encoders were reset.
+    pub fn read_distances(&mut self) -> Result<[Meters; 2], RoboClawError> {
+        // The counts are signed positions carried in an unsigned register.
+        let counts = self.roboclaw.read_encoders()?;
+        Ok([0, 1].map(|i| self.wheels[i].distance(counts[i] as i32 as i64)))
+    }
+
+    /// Reads the speeds of both wheels.
+    pub fn read_speeds(&mut self) -> Result<[MetersPerSecond; 2], RoboClawError> {
+        let speeds = self.roboclaw.read_speeds()?;
+        Ok([0, 1].map(|i| self.wheels[i].speed(speeds[i])))
+    }
+
+    /// Reads the angular speeds of both wheels.
+    pub fn read_angular_speeds(&mut self) -> Result<[RadiansPerSecond; 2], RoboClawError> {
+        let speeds = self.roboclaw.read_speeds()?;
+        Ok([0, 1].map(|i| self.wheels[i].angular_speed(speeds[i])))
+    }
+
+    /// Reads the currents of both motors.
+    pub fn read_currents(&mut self) -> Result<[Amps; 2], RoboClawError> {
+        Ok(self.roboclaw.read_currents()?.map(Amps::from_raw))
+    }
+
+    /// Reads the main battery voltage.
+    pub fn read_main_battery(&mut self) -> Result<Volts, RoboClawError> {
+        Ok(Volts::from_raw(self.roboclaw.read_main_battery_voltage()?))
+    }
+
+    /// Reads the logic battery voltage.
+    pub fn read_logic_battery(&mut self) -> Result<Volts, RoboClawError> {
+        Ok(Volts::from_raw(self.roboclaw.read_logic_battery_voltage()?))
+    }
+
+    /// Reads the board temperature.
+    pub fn read_temperature(&mut self) -> Result<Celsius, RoboClawError> {
+        Ok(Celsius::from_raw(self.roboclaw.read_temperature()?))
+    }
+
+    /// Reads the second temperature sensor, only fitted to some models.
+    pub fn read_temperature_2(&mut self) -> Result<Celsius, RoboClawError> {
+        Ok(Celsius::from_raw(self.roboclaw.read_temperature_2()?))
+    }
+
+    /// Builds the command for each motor addressed by `motor` from its index and
+    /// wheel and sends it with `Roboclaw::send` or `Roboclaw::send_each`.
+    fn send<C: MotorCommand>(
+        &mut self,
+        motor: Motor,
+        command: impl Fn(usize, &Wheel) -> Result<C, OutOfRange>,
+    ) -> Result<bool, RoboClawError> {
+        let address = self.roboclaw.connection.address;
+        let build = |index: usize| {
+            let name = carrier::<C>(motor, index);
+            command(index, &self.wheels[index]).map_err(|error| error.into_error(name, address))
+        };
+        match motor {
+            Motor::M1 => {
+                let m1 = build(0)?;
+                self.roboclaw.send(Motor::M1, m1)
+            }
+            Motor::M2 => {
+                let m2 = build(1)?;
+                self.roboclaw.send(Motor::M2, m2)
+            }
+            Motor::Both => {
+                let (m1, m2) = (build(0)?, build(1)?);
+                self.roboclaw.send_each(m1, m2)
+            }
+        }
+    }
+}
+
+/// Converts `speed` to counts per second on `wheel`.
+fn speed_counts(wheel: &Wheel, speed: MetersPerSecond) -> Result<i32, OutOfRange> {
+    let counts = checked(
+        "speed",
+        wheel.speed_counts(speed),
+        i32::MIN.into(),
+        i32::MAX.into(),
+    )?;
+    Ok(counts as i32)
+}
+
+/// Converts `accel` to counts per second squared on `wheel`.
+fn accel_counts(wheel: &Wheel, accel: MetersPerSecondSquared) -> Result<u32, OutOfRange> {
+    let counts = checked("accel", wheel.accel_counts(accel), 0, u32::MAX.into())?;
+    Ok(counts as u32)
+}
+
+/// Converts `distance` to counts on `wheel`.
+fn distance_counts(wheel: &Wheel, distance: Meters) -> Result<u32, OutOfRange> {
+    let counts = checked(
+        "distance",
+        wheel.distance_counts(distance),
+        0,
+        u32::MAX.into(),
+    )?;
+    Ok(counts as u32)
+}
+
+/// Checks rounded `counts` for `argument` against `min..=max`, the range of the
+/// argument's integer type.
+fn checked(argument: &'static str, counts: f64, min: i64, max: i64) -> Result<i64, OutOfRange> {
+    // `as` saturates, so counts beyond i64 still fail the check. NaN would
+    // convert to 0 and is replaced to fail as well.
+    let value = if counts.is_nan() {
+        i64::MIN
+    } else {
+        counts as i64
+    };
+    OutOfRange::check(argument, value, min, max)?;
+    Ok(value)
+}
+
+impl Roboclaw {
+    /// Returns a view sending and reading physical quantities, with `wheels`
+    /// describing the wheels of M1 and M2. See the `units` module.
+    pub fn units(&mut self, wheels: [Wheel; 2]) -> Units<'_> {
+        Units::new(self, wheels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::Commands,
+        errors::RoboClawError,
+        replay::{
+            tests::{ack, write, ADDRESS},
+            ReplayTransport,
+        },
+    };
+
+    /// A wheel with 1000 counts per revolution and per metre.
+    fn wheel() -> Wheel {
+        Wheel::new(500.0, 2.0, Meters(1.0 / (2.0 * PI)))
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn counts_convert_to_quantities() {
+        let wheel = wheel();
+        assert_close(wheel.counts_per_wheel_revolution(), 1000.0);
+        assert_close(wheel.counts_per_meter(), 1000.0);
+        assert_close(wheel.distance(2500).0, 2.5);
+        assert_close(wheel.speed(-1000).0, -1.0);
+        assert_close(wheel.angle(500).0, PI);
+        assert_close(wheel.angular_speed(-250).0, -PI / 2.0);
+
+        assert_eq!(Amps::from_raw(-150), Amps(-1.5));
+        assert_eq!(Volts::from_raw(124), Volts(12.4));
+        assert_eq!(Celsius::from_raw(315), Celsius(31.5));
+    }
+
+    #[test]
+    fn quantities_round_to_the_nearest_count() {
+        let wheel = wheel();
+        assert_eq!(wheel.distance_counts(Meters(0.0014)), 1.0);
+        assert_eq!(wheel.distance_counts(Meters(0.0016)), 2.0);
+        assert_eq!(wheel.speed_counts(MetersPerSecond(-0.5)), -500.0);
+        assert_eq!(wheel.accel_counts(MetersPerSecondSquared(2.0)), 2000.0);
+    }
+
+    #[test]
+    fn counts_outside_the_argument_type_are_rejected() {
+        let wheel = wheel();
+        assert_eq!(speed_counts(&wheel, MetersPerSecond(-2.0)), Ok(-2000));
+        assert_eq!(
+            speed_counts(&wheel, MetersPerSecond(3e6)),
+            Err(OutOfRange {
+                argument: "speed",
+                value: 3_000_000_000,
+                min: i32::MIN.into(),
+                max: i32::MAX.into(),
+            })
+        );
+        assert_eq!(
+            accel_counts(&wheel, MetersPerSecondSquared(-1.0)).map_err(|error| error.value),
+            Err(-1000)
+        );
+        assert_eq!(
+            distance_counts(&wheel, Meters(-0.5)).map_err(|error| error.argument),
+            Err("distance")
+        );
+        // Values beyond i64 saturate and NaN is replaced, so both still fail.
+        assert_eq!(
+            distance_counts(&wheel, Meters(1e30)).map_err(|error| error.value),
+            Err(i64::MAX)
+        );
+        assert_eq!(
+            speed_counts(&wheel, MetersPerSecond(f64::NAN)).map_err(|error| error.value),
+            Err(i64::MIN)
+        );
+    }
+
+    #[test]
+    fn speeds_are_sent_in_counts_of_the_addressed_wheel() {
+        let frames = vec![
+            write(Commands::M2DriveSignedSpeed, &(-250i32).to_be_bytes()),
+            ack(Commands::M2DriveSignedSpeed),
+        ];
+        let transport = ReplayTransport::from_frames(frames);
+        let handle = transport.handle();
+        let mut roboclaw = Roboclaw::from_transport(transport, ADDRESS).unwrap();
+        let slow = Wheel::new(1.0, 1.0, Meters(1.0));
+        let mut units = roboclaw.units([slow, wheel()]);
+
+        assert!(units.set_speed(Motor::M2, MetersPerSecond(-0.25)).unwrap());
+        // Errors name the command of the motor whose speed is out of range.
+        assert!(matches!(
+            units.set_speed(Motor::M2, MetersPerSecond(3e6)),
+            Err(RoboClawError::InvalidArgument {
+                command: Commands::M2DriveSignedSpeed,
+                argument: "speed",
+                ..
+            })
+        ));
+        handle.assert_finished();
+    }
+}